@@ -1,19 +1,215 @@
-use std::path::PathBuf;
+use core::fmt;
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::PathBuf,
+};
 
-use clap::Parser;
+use anyhow::Context;
+use clap::{Parser, ValueEnum};
 use shr::Shr;
 
 #[derive(Debug, Parser)]
 #[command(version, about)]
 pub struct Args {
-    /// The directory to scan.
-    #[clap()]
-    dir: PathBuf,
+    /// The directories to scan. The directories nested inside another one are
+    /// skipped.
+    #[clap(required_unless_present_any = ["import", "connect", "input", "stdin", "replay"])]
+    dirs: Vec<PathBuf>,
+
+    /// Loads the entries from an ncdu export instead of scanning.
+    #[clap(long, value_name = "FILE", conflicts_with = "dirs")]
+    import: Option<PathBuf>,
+
+    /// Receives the scan from a remote `shr agent` instead of scanning, e.g.
+    /// `host:7878`.
+    #[clap(long, value_name = "ADDR", conflicts_with_all = ["dirs", "import"])]
+    connect: Option<String>,
+
+    /// Reads a serialized event stream in JSON lines from the file instead of
+    /// scanning, e.g. the output of `shr --format json` or `--format stream`.
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["dirs", "import", "connect"])]
+    input: Option<PathBuf>,
+
+    /// Reads a serialized event stream from stdin instead of scanning, see
+    /// `--input`.
+    #[clap(long, conflicts_with_all = ["dirs", "import", "connect", "input"])]
+    stdin: bool,
+
+    /// Replays an event log recorded by `shr --format log` with the original
+    /// timing instead of scanning.
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["dirs", "import", "connect", "input", "stdin"]
+    )]
+    replay: Option<PathBuf>,
+
+    /// Breaks down the usage of each directory by owner (uid/gid).
+    #[clap(long)]
+    by_owner: bool,
+
+    /// Breaks down the usage of each directory by age buckets, which enables
+    /// colouring the ratio bars by age.
+    #[clap(long)]
+    ages: bool,
+
+    /// The timestamp used to determine the age of files.
+    #[clap(long, default_value_t = AgeBy::Mtime)]
+    age_by: AgeBy,
+
+    /// The number of threads to scan, which defaults to all the cores.
+    #[clap(long, env = "SHR_THREADS")]
+    threads: Option<usize>,
+
+    /// The units of the sizes, which defaults to `si`.
+    #[clap(long, env = "SHR_UNITS")]
+    pub units: Option<Units>,
+
+    /// Skips the entries matching the glob, e.g. `node_modules`, which matches
+    /// at any depth unless it begins with `/`.
+    #[clap(long, value_name = "GLOB", env = "SHR_EXCLUDE", value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Applies the named profile of the config files, e.g. `ci`.
+    #[clap(long, value_name = "NAME", env = "SHR_PROFILE")]
+    profile: Option<String>,
+
+    /// The number of the largest files listed.
+    #[clap(long, value_name = "N", default_value_t = 100)]
+    largest: usize,
+
+    /// Finds the duplicate files after the scan.
+    #[clap(long)]
+    pub dupes: bool,
+
+    /// Serves the scan as a JSON API at the address instead of opening the
+    /// window, e.g. `127.0.0.1:8080`.
+    #[clap(long, value_name = "ADDR", conflicts_with = "dupes")]
+    pub serve: Option<SocketAddr>,
+
+    /// Serves a web frontend along with the JSON API instead of opening the
+    /// window, at the `--serve` address or `127.0.0.1:8080`.
+    #[clap(long, conflicts_with = "dupes")]
+    pub web: bool,
 }
 
+/// The address to serve the web frontend, unless `--serve` is given.
+pub const DEFAULT_ADDR: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8080));
+
 impl Args {
-    /// Builds the `shr` instance.
-    pub async fn build(self) -> shr::ShrRx {
-        Shr::new(self.dir).with_max_depth(usize::MAX).run().await
+    /// Fills the options not given by the flags or the environment variables
+    /// from the config files, see [`shr::Config`].
+    pub fn apply_config(&mut self) -> anyhow::Result<()> {
+        let config = shr::Config::load(self.profile.as_deref())?;
+        if let (None, Some(units)) = (self.units, &config.units) {
+            let units = Units::from_str(units, true)
+                .map_err(|e| anyhow::anyhow!("invalid units in config: {e}"))?;
+            self.units = Some(units);
+        }
+        self.threads = self.threads.or(config.threads);
+        self.exclude.splice(0..0, config.exclude);
+        Ok(())
+    }
+
+    /// Builds the `shr` instance, or loads the entries to import, read,
+    /// replay or receive.
+    pub async fn build(self) -> anyhow::Result<shr::ShrRx> {
+        if let Some(addr) = &self.connect {
+            return shr::connect_agent(addr.as_str(), Some(self.largest)).await;
+        }
+        if let Some(file) = &self.input {
+            let reader = std::fs::File::open(file)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            return shr::read_events(std::io::BufReader::new(reader), Some(self.largest));
+        }
+        if let Some(file) = &self.replay {
+            let reader = std::fs::File::open(file)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            return shr::Replay::new(std::io::BufReader::new(reader))
+                .with_largest(Some(self.largest))
+                .with_timing(true)
+                .run();
+        }
+        if self.stdin {
+            let reader = std::io::BufReader::new(std::io::stdin());
+            return shr::read_events(reader, Some(self.largest));
+        }
+        if let Some(file) = &self.import {
+            let reader = std::fs::File::open(file)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            return shr::load_ncdu(std::io::BufReader::new(reader), Some(self.largest));
+        }
+
+        let rx = Shr::from_roots(self.dirs)
+            .with_max_depth(usize::MAX)
+            .with_owners(self.by_owner)
+            .with_ages(self.ages.then(|| self.age_by.into()))
+            .with_largest(Some(self.largest))
+            .with_threads(self.threads.unwrap_or(0))
+            .with_excludes(
+                self.exclude
+                    .iter()
+                    .map(|glob| shr::Glob::new(glob))
+                    .collect(),
+            )
+            .run()
+            .await;
+        Ok(rx)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum Units {
+    /// Powers of 1000, e.g. `1.5M`.
+    #[default]
+    Si,
+    /// Powers of 1024, e.g. `1.4M`.
+    Binary,
+    /// The bytes, e.g. `1500000B`.
+    Bytes,
+}
+
+impl Units {
+    /// Gets the format of [`shr::utils::human_readable_number`].
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Units::Si => "si",
+            Units::Binary => "",
+            Units::Bytes => "b",
+        }
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Units::Si => write!(f, "si"),
+            Units::Binary => write!(f, "binary"),
+            Units::Bytes => write!(f, "bytes"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum AgeBy {
+    Mtime,
+    Atime,
+}
+
+impl fmt::Display for AgeBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgeBy::Mtime => write!(f, "mtime"),
+            AgeBy::Atime => write!(f, "atime"),
+        }
+    }
+}
+
+impl From<AgeBy> for shr::Timestamp {
+    fn from(by: AgeBy) -> Self {
+        match by {
+            AgeBy::Mtime => shr::Timestamp::Modified,
+            AgeBy::Atime => shr::Timestamp::Accessed,
+        }
     }
 }
@@ -0,0 +1,86 @@
+//! Finds the duplicate files after the scan and lists them.
+
+use std::{process::Termination, rc::Rc};
+
+use shr::{DupeFinder, DupeGroup, utils::human_readable_number};
+use slint::{SharedString, VecModel, Weak};
+
+use crate::{args::Units, ui::*};
+
+/// Shows what the duplicates view is waiting for.
+pub fn waiting_ui_change(ui_handle: &Weak<AppWindow>, enabled: bool) {
+    let status = if enabled {
+        "Waiting for the scan to finish."
+    } else {
+        "Run with --dupes to find duplicate files."
+    };
+    set_status(ui_handle, status.into());
+}
+
+/// Confirms the duplicates by content hashes and lists them.
+pub async fn find(finder: DupeFinder, units: Units, ui_handle: Weak<AppWindow>) {
+    set_status(&ui_handle, "Hashing the candidate files.".into());
+
+    let groups = match tokio::task::spawn_blocking(move || finder.find()).await {
+        Ok(groups) => groups,
+        Err(err) => {
+            set_status(
+                &ui_handle,
+                format!("Failed to find duplicates: {err}").into(),
+            );
+            return;
+        }
+    };
+
+    let reclaimable = groups.iter().map(DupeGroup::reclaimable).sum::<u64>();
+    let status = format!(
+        "{} duplicate group(s), {} reclaimable",
+        groups.len(),
+        human_readable_number(reclaimable, units.as_str())
+    );
+    let rows = groups
+        .iter()
+        .map(|group| dupe_row(group, units))
+        .collect::<Vec<_>>();
+
+    slint::invoke_from_event_loop(move || {
+        let Some(ui) = ui_handle.upgrade() else {
+            return;
+        };
+
+        ui.set_dupes_status(status.into());
+        ui.set_dupes(Rc::new(VecModel::from(rows)).into());
+    })
+    .report();
+}
+
+fn dupe_row(group: &DupeGroup, units: Units) -> DupeRow {
+    let title = format!(
+        "{} copies of {}, {} reclaimable",
+        group.paths.len(),
+        human_readable_number(group.size, units.as_str()),
+        human_readable_number(group.reclaimable(), units.as_str())
+    );
+    let paths = group
+        .paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    DupeRow {
+        title: title.into(),
+        paths: paths.into(),
+    }
+}
+
+fn set_status(ui_handle: &Weak<AppWindow>, status: SharedString) {
+    let ui_handle = ui_handle.clone();
+    slint::invoke_from_event_loop(move || {
+        let Some(ui) = ui_handle.upgrade() else {
+            return;
+        };
+
+        ui.set_dupes_status(status);
+    })
+    .report();
+}
@@ -6,21 +6,41 @@
 // when, e.g., starting the app via file manager. Ignored on other platforms.
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::{collections::HashMap, io, num::NonZeroUsize, process::Termination, rc::Rc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    num::NonZeroUsize,
+    process::Termination,
+    rc::Rc,
+};
 
 mod ui;
 
 mod args;
+mod dupes;
+mod server;
 
 use args::Args;
 use clap::Parser;
-use shr::{Event, EventRef, PathId, ShrRx, utils::human_readable_number};
+use shr::{
+    AgeBucket, AgeUsage, DupeFinder, Event, Owner, OwnerNames, OwnerUsage, PathId, ShrRx,
+    display_path, utils::human_readable_number,
+};
 use slint::{ComponentHandle, LogicalSize, SharedString, ToSharedString, VecModel, Weak};
 use ui::*;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let mut rx = Args::parse().build().await;
+    let mut args = Args::parse();
+    args.apply_config()?;
+    let units = args.units.unwrap_or_default();
+    let find_dupes = args.dupes;
+    let web = args.web;
+    let serve = args.serve.or(web.then_some(args::DEFAULT_ADDR));
+    let mut rx = args.build().await?;
+    if let Some(addr) = serve {
+        return server::serve(addr, web, rx).await;
+    }
 
     let handle = tokio::runtime::Handle::current();
     let ui_thread = std::thread::spawn(move || {
@@ -58,6 +78,19 @@ async fn main() -> anyhow::Result<()> {
             }
         });
 
+        ui.on_filter_owner({
+            let ui_handle = ui.as_weak();
+            let ui_tx = ui_tx.clone();
+
+            move |index| {
+                let Some(_ui) = ui_handle.upgrade() else {
+                    return;
+                };
+                eprintln!("filter owner: {index}");
+                let _ = ui_tx.send(UiEvent::FilterOwner(index));
+            }
+        });
+
         // let rnk = ui.get_shr_rnk();
         // rnk.sort();
 
@@ -65,8 +98,12 @@ async fn main() -> anyhow::Result<()> {
         handle.spawn(async move {
             let mut path_tree = PathTree {
                 begin: Some(std::time::Instant::now()),
+                owner_names: OwnerNames::load(),
+                dupes: find_dupes.then(DupeFinder::default),
+                units,
                 ..PathTree::default()
             };
+            dupes::waiting_ui_change(&ui_handle, find_dupes);
 
             loop {
                 tokio::select! {
@@ -77,6 +114,8 @@ async fn main() -> anyhow::Result<()> {
 
                         path_tree.event_cnt += 1;
 
+                        let event = event.to_raw();
+                        path_tree.collect_dupe(&rx, &event);
                         path_tree.process_change(event);
                         path_tree.ui_change(&ui_handle, &rx);
                     }
@@ -100,6 +139,12 @@ async fn main() -> anyhow::Result<()> {
                 .report();
             }
 
+            path_tree.largest_ui_change(&ui_handle, &rx);
+
+            if let Some(finder) = path_tree.dupes.take() {
+                tokio::spawn(dupes::find(finder, path_tree.units, ui_handle.clone()));
+            }
+
             loop {
                 let Some(event) = ui_rx.recv().await else {
                     break;
@@ -130,6 +175,18 @@ struct PathTree {
     event_cnt: u64,
     focus: Option<PathId>,
     focus_affected: bool,
+    owner_names: OwnerNames,
+    /// The users seen in the scan, listed in the owner filter.
+    owner_choices: BTreeSet<u32>,
+    owners_affected: bool,
+    /// Only shows the usage of the user if set.
+    owner_filter: Option<u32>,
+    has_ages: bool,
+    ages_affected: bool,
+    /// The duplicate finder, if requested.
+    dupes: Option<DupeFinder>,
+    /// The units of the sizes.
+    units: args::Units,
 }
 
 impl PathTree {
@@ -144,8 +201,85 @@ impl PathTree {
         }
     }
 
-    fn process_change(&mut self, event: EventRef) {
-        let event = event.to_raw();
+    fn update_parent_owners(&mut self, parent_id: Option<PathId>, owners: &OwnerUsage) {
+        let parent = self.paths.entry(parent_id).or_default();
+        parent.owners.get_or_insert_default().merge(owners);
+    }
+
+    fn update_parent_ages(&mut self, parent_id: Option<PathId>, ages: &AgeUsage) {
+        if !self.has_ages {
+            self.has_ages = true;
+            self.ages_affected = true;
+        }
+        let parent = self.paths.entry(parent_id).or_default();
+        parent.ages.get_or_insert_default().merge(ages);
+    }
+
+    /// Gets the ratios of the age buckets in the bar.
+    fn age_ratios(&self, node: &PathSlot) -> Ages {
+        let Some(ages) = &node.ages else {
+            return Ages::default();
+        };
+        let total = AgeBucket::ALL
+            .map(|bucket| ages.get(bucket))
+            .iter()
+            .sum::<u64>();
+        let ratio = |bucket| Some((ages.get(bucket), total)).to_ratio();
+        Ages {
+            week: ratio(AgeBucket::Week),
+            month: ratio(AgeBucket::Month),
+            half_year: ratio(AgeBucket::HalfYear),
+            older: ratio(AgeBucket::Older),
+        }
+    }
+
+    fn add_owner_choice(&mut self, uid: u32) {
+        if self.owner_choices.insert(uid) {
+            self.owners_affected = true;
+        }
+    }
+
+    /// Gets the size of the node, only counting the filtered owner.
+    fn filtered_size(&self, node: &PathSlot) -> Option<u64> {
+        let Some(uid) = self.owner_filter else {
+            return node.size;
+        };
+        if node.is_file {
+            let owned = node.owner.is_some_and(|owner| owner.uid == uid);
+            return node.size.map(|size| if owned { size } else { 0 });
+        }
+        let usage = node
+            .owners
+            .as_ref()
+            .and_then(|owners| owners.users.get(&uid));
+        Some(usage.map_or(0, |usage| usage.size))
+    }
+
+    /// Displays the owner of a file or the top user of a directory.
+    fn owner_text(&self, node: &PathSlot) -> SharedString {
+        if let Some(owner) = node.owner {
+            return self.owner_names.display_user(owner.uid).into();
+        }
+        let Some((uid, usage)) = node.owners.as_ref().and_then(|owners| owners.top_user()) else {
+            return SharedString::default();
+        };
+        let ratio = Some((usage.size, node.size.unwrap_or(0))).to_ratio();
+        let name = self.owner_names.display_user(uid);
+        format!("{name} {:.0}%", ratio * 100.).into()
+    }
+
+    fn collect_dupe(&mut self, rx: &ShrRx, event: &Event) {
+        let Some(finder) = &mut self.dupes else {
+            return;
+        };
+        if let Event::FileFinish { path, size, .. } = event {
+            if let Some(path) = rx.get_path(*path) {
+                finder.add(path, *size);
+            }
+        }
+    }
+
+    fn process_change(&mut self, event: Event) {
         match event {
             Event::Dir { path, parent } => {
                 let parent_cell = self.paths.entry(parent).or_default();
@@ -165,14 +299,38 @@ impl PathTree {
                     self.focus_affected = true;
                 }
             }
-            Event::FileFinish { path, parent, size } => {
+            Event::FileFinish {
+                path,
+                parent,
+                size,
+                meta,
+            } => {
                 let parent_cell = self.paths.entry(parent).or_default();
                 parent_cell.children.push(path);
 
+                let owner = meta.as_ref().and_then(|meta| meta.owner);
+                let ages = meta.as_ref().and_then(|meta| {
+                    let mut ages = AgeUsage::default();
+                    ages.add_file(meta.age?, None, size);
+                    Some(ages)
+                });
                 let child = self.paths.entry(Some(path)).or_default();
                 child.size = Some(size);
                 child.parent = parent;
                 child.is_file = true;
+                child.owner = owner;
+                child.ages = ages;
+
+                if let Some(ages) = &ages {
+                    self.update_parent_ages(parent, ages);
+                }
+
+                if let Some(owner) = owner {
+                    let mut owners = OwnerUsage::default();
+                    owners.add_file(owner, size);
+                    self.update_parent_owners(parent, &owners);
+                    self.add_owner_choice(owner.uid);
+                }
 
                 self.total_files += 1;
                 self.process_events += 1;
@@ -190,14 +348,30 @@ impl PathTree {
                 path,
                 size,
                 num_files,
+                meta,
             } => {
                 let child = self.paths.entry(Some(path)).or_default();
                 let parent = child.parent;
                 self.update_parent_size(parent, size, num_files);
 
+                let (owners, ages) = meta.map(|meta| (meta.owners, meta.ages)).unzip();
+                let (owners, ages) = (owners.flatten(), ages.flatten());
+                if let Some(owners) = &owners {
+                    self.update_parent_owners(parent, owners);
+                }
+                if let Some(ages) = &ages {
+                    self.update_parent_ages(parent, ages);
+                }
+
                 let child = self.paths.entry(Some(path)).or_default();
                 child.size = Some(size);
                 child.files = num_files;
+                if let Some(owners) = owners {
+                    child.owners = Some(Box::new(owners));
+                }
+                if ages.is_some() {
+                    child.ages = ages;
+                }
 
                 self.in_process_files -= 1;
                 self.process_events += 1;
@@ -206,6 +380,27 @@ impl PathTree {
                     self.focus_affected = true;
                 }
             }
+            Event::Total {
+                size,
+                num_files,
+                meta,
+            } => {
+                let root = self.paths.entry(None).or_default();
+                root.size = Some(size);
+                root.files = num_files;
+                if let Some(meta) = meta {
+                    if let Some(owners) = meta.owners {
+                        root.owners = Some(Box::new(owners));
+                    }
+                    if meta.ages.is_some() {
+                        root.ages = meta.ages;
+                    }
+                }
+
+                if !self.focus_affected && self.focus.is_none() {
+                    self.focus_affected = true;
+                }
+            }
         }
     }
 
@@ -225,20 +420,115 @@ impl PathTree {
                 self.focus_affected = true;
                 self.ui_change(ui_handle, rx);
             }
+            UiEvent::FilterOwner(index) => {
+                // The first choice is "All owners".
+                let index = usize::try_from(index).unwrap_or(0);
+                self.owner_filter = index
+                    .checked_sub(1)
+                    .and_then(|index| self.owner_choices.iter().nth(index).copied());
+                self.focus_affected = true;
+                self.ui_change(ui_handle, rx);
+            }
         }
     }
 
     fn ui_change(&mut self, ui_handle: &Weak<AppWindow>, rx: &ShrRx) {
-        self.in_process_ui_change(ui_handle);
+        self.in_process_ui_change(ui_handle, rx);
+        self.owners_ui_change(ui_handle);
+        self.ages_ui_change(ui_handle);
         self.focus_ui_change(ui_handle, rx);
     }
 
-    fn in_process_ui_change(&mut self, ui_handle: &Weak<AppWindow>) {
+    fn ages_ui_change(&mut self, ui_handle: &Weak<AppWindow>) {
+        if !self.ages_affected {
+            return;
+        }
+        self.ages_affected = false;
+
+        let ui_handle = ui_handle.clone();
+        slint::invoke_from_event_loop(move || {
+            let Some(ui) = ui_handle.upgrade() else {
+                return;
+            };
+
+            ui.set_has_ages(true);
+        })
+        .report();
+    }
+
+    fn owners_ui_change(&mut self, ui_handle: &Weak<AppWindow>) {
+        if !self.owners_affected {
+            return;
+        }
+        self.owners_affected = false;
+
+        let choices = std::iter::once(SharedString::from("All owners"))
+            .chain(
+                self.owner_choices
+                    .iter()
+                    .map(|uid| self.owner_names.display_user(*uid).into()),
+            )
+            .collect::<Vec<_>>();
+
+        let ui_handle = ui_handle.clone();
+        slint::invoke_from_event_loop(move || {
+            let Some(ui) = ui_handle.upgrade() else {
+                return;
+            };
+
+            ui.set_owners(Rc::new(VecModel::from(choices)).into());
+        })
+        .report();
+    }
+
+    fn in_process_ui_change(&mut self, ui_handle: &Weak<AppWindow>, rx: &ShrRx) {
         if self.process_events & 0xfff != 0 {
             return;
         }
 
         self.change_in_process_ui(ui_handle);
+        self.largest_ui_change(ui_handle, rx);
+    }
+
+    fn largest_ui_change(&self, ui_handle: &Weak<AppWindow>, rx: &ShrRx) {
+        let Some(largest) = rx.largest() else {
+            return;
+        };
+
+        let root_size = self
+            .paths
+            .get(&None)
+            .and_then(|root| root.size)
+            .filter(|s| *s > 0);
+
+        let v = largest
+            .files()
+            .into_iter()
+            .filter_map(|(path, size)| {
+                let node = self.paths.get(&Some(path));
+                let display = display_path(&rx.get_path(path)?).as_ref().into();
+                Some(Rank {
+                    path_id: path.into_raw().get().to_shared_string(),
+                    path: display,
+                    size: human_readable_number(size, self.units.as_str()).to_shared_string(),
+                    ratio: Some(size).zip(root_size).to_ratio(),
+                    all_ratio: Some(size).zip(root_size).to_ratio(),
+                    is_file: true,
+                    owner: node.map(|n| self.owner_text(n)).unwrap_or_default(),
+                    ages: node.map(|n| self.age_ratios(n)).unwrap_or_default(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let ui_handle = ui_handle.clone();
+        slint::invoke_from_event_loop(move || {
+            let Some(ui) = ui_handle.upgrade() else {
+                return;
+            };
+
+            ui.set_largest(Rc::new(VecModel::from(v)).into());
+        })
+        .report();
     }
 
     fn change_in_process_ui(&mut self, ui_handle: &Weak<AppWindow>) {
@@ -270,15 +560,15 @@ impl PathTree {
         let root_size = self
             .paths
             .get(&None)
-            .and_then(|root| root.size)
+            .and_then(|root| self.filtered_size(root))
             .filter(|s| *s > 0);
 
         let node = self.paths.get(&self.focus);
 
         let parent_size = node
             .and_then(|n| n.parent)
-            .and_then(|n| (self.paths.get(&Some(n))))
-            .and_then(|parent| parent.size);
+            .and_then(|n| self.paths.get(&Some(n)))
+            .and_then(|parent| self.filtered_size(parent));
 
         let mut v = Vec::default();
 
@@ -293,36 +583,49 @@ impl PathTree {
                     .to_shared_string(),
                 path: self
                     .focus
-                    .map(|focus| rx.get_path(focus).unwrap().to_str().unwrap().into())
+                    .map(|focus| {
+                        rx.get_path(focus)
+                            .map(|path| display_path(&path).as_ref().into())
+                            .unwrap_or_default()
+                    })
+                    .unwrap_or_else(|| "All roots".into()),
+                size: self
+                    .filtered_size(node)
+                    .map(|s| human_readable_number(s, self.units.as_str()).to_shared_string())
                     .unwrap_or_default(),
-                size: node
-                    .size
-                    .map(|s| human_readable_number(s, "si").to_shared_string())
-                    .unwrap_or_default(),
-                ratio: node.size.zip(parent_size).to_ratio(),
-                all_ratio: node.size.zip(root_size).to_ratio(),
+                ratio: self.filtered_size(node).zip(parent_size).to_ratio(),
+                all_ratio: self.filtered_size(node).zip(root_size).to_ratio(),
                 is_file: node.is_file,
+                owner: self.owner_text(node),
+                ages: self.age_ratios(node),
             });
 
-            let parent_size = node.size;
+            let parent_size = self.filtered_size(node);
 
             v = node
                 .children
                 .iter()
-                .map(|p| {
+                .filter_map(|p| {
+                    let path = rx.get_path(*p)?;
                     let node = self.paths.get(&Some(*p));
-                    let size = node.and_then(|n| n.size);
+                    let size = node.and_then(|n| self.filtered_size(n));
+                    if self.owner_filter.is_some() && size == Some(0) {
+                        return None;
+                    }
                     let ratio = size.zip(parent_size).to_ratio();
                     let all_ratio = size.zip(root_size).to_ratio();
 
-                    Rank {
+                    Some(Rank {
                         path_id: p.into_raw().get().to_shared_string(),
-                        path: rx.get_path(*p).unwrap().to_str().unwrap().into(),
-                        size: human_readable_number(size.unwrap_or(0), "si").to_shared_string(),
+                        path: display_path(&path).as_ref().into(),
+                        size: human_readable_number(size.unwrap_or(0), self.units.as_str())
+                            .to_shared_string(),
                         ratio,
                         all_ratio,
                         is_file: node.map(|n| n.is_file).unwrap_or(true),
-                    }
+                        owner: node.map(|n| self.owner_text(n)).unwrap_or_default(),
+                        ages: node.map(|n| self.age_ratios(n)).unwrap_or_default(),
+                    })
                 })
                 .collect();
 
@@ -356,11 +659,18 @@ struct PathSlot {
     size: Option<u64>,
     files: usize,
     is_file: bool,
+    /// The owner of the file.
+    owner: Option<Owner>,
+    /// The usage of the directory grouped by owners.
+    owners: Option<Box<OwnerUsage>>,
+    /// The usage grouped by ages.
+    ages: Option<AgeUsage>,
 }
 
 enum UiEvent {
     GotoParent,
     GotoPath(SharedString),
+    FilterOwner(i32),
 }
 
 trait ToRatio {
@@ -0,0 +1,352 @@
+//! Serves the scan over HTTP as a JSON API, without the window.
+//!
+//! - `GET /api/status`: the progress of the scan.
+//! - `GET /api/children?id=N`: the children of the path id sorted by size, or
+//!   the roots if the id is omitted or zero.
+//! - `GET /api/search?q=TEXT&limit=N`: the paths containing the text,
+//!   ignoring the case, sorted by size.
+//! - `GET /api/events`: the scan events as server-sent events, each of which
+//!   is a record of the compact event stream in JSON.
+//!
+//! With `--web`, a web frontend mirroring the window is served at `/`, which
+//! polls the API to update the view during the scan.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use http_body_util::{BodyExt, Full, channel::Channel, combinators::BoxBody};
+use hyper::{
+    Method, Request, Response, StatusCode, body::Bytes, server::conn::http1, service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use serde_json::{Value, json};
+use shr::{Event, PathId, PathResolver, ShrRx, StreamEncoder, StreamRecord, display_path};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{PathTree, ToRatio};
+
+/// The number of events buffered for each subscriber of `/api/events`.
+const EVENT_BUFFER: usize = 4096;
+/// The number of the search results, unless `limit` is given.
+const SEARCH_LIMIT: usize = 100;
+
+/// The web frontend.
+const INDEX: &str = include_str!("../web/index.html");
+
+/// The body of the responses.
+type Body = BoxBody<Bytes, Infallible>;
+
+/// The scan shared with the requests.
+struct State {
+    /// The tree updated by the events.
+    tree: Mutex<PathTree>,
+    /// Whether the scan is finished.
+    finished: Mutex<bool>,
+    /// Resolves the path ids.
+    paths: PathResolver,
+    /// Broadcasts the events to the subscribers, which is dropped after the
+    /// scan finishes.
+    events: Mutex<Option<broadcast::Sender<Event>>>,
+    /// Whether to serve the web frontend.
+    web: bool,
+}
+
+/// Serves the scan at the address, until the process is killed.
+pub async fn serve(addr: SocketAddr, web: bool, mut rx: ShrRx) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to listen on {addr}"))?;
+    eprintln!("serving at http://{addr}");
+
+    let state = Arc::new(State {
+        tree: Mutex::new(PathTree {
+            begin: Some(std::time::Instant::now()),
+            ..PathTree::default()
+        }),
+        finished: Mutex::new(false),
+        paths: rx.resolver(),
+        events: Mutex::new(Some(broadcast::channel(EVENT_BUFFER).0)),
+        web,
+    });
+
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            while let Some(event) = rx.recv().await {
+                let event = event.to_raw();
+                if let Some(events) = state.events.lock().unwrap().as_ref() {
+                    if events.receiver_count() > 0 {
+                        let _ = events.send(event.clone());
+                    }
+                }
+                let mut tree = state.tree.lock().unwrap();
+                tree.event_cnt += 1;
+                tree.process_change(event);
+            }
+            *state.finished.lock().unwrap() = true;
+            state.events.lock().unwrap().take();
+            eprintln!("Finished processing");
+        }
+    });
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service = service_fn(|req| {
+                let response = respond(&req, &state);
+                async move { Ok::<_, Infallible>(response) }
+            });
+            let conn = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            if let Err(e) = conn.await {
+                eprintln!("failed to serve connection: {e}");
+            }
+        });
+    }
+}
+
+/// Routes the request.
+fn respond<B>(req: &Request<B>, state: &Arc<State>) -> Response<Body> {
+    if req.method() != Method::GET {
+        return text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed\n");
+    }
+    let query = parse_query(req.uri().query().unwrap_or_default());
+    match req.uri().path() {
+        "/api/status" => json_response(status(state)),
+        "/api/children" => {
+            let id = match query.get("id").map(|id| id.parse::<usize>()) {
+                None | Some(Ok(0)) => None,
+                Some(Ok(id)) => Some(PathId::from_raw(NonZeroUsize::new(id).unwrap())),
+                Some(Err(_)) => return text(StatusCode::BAD_REQUEST, "invalid id\n"),
+            };
+            match children(state, id) {
+                Some(children) => json_response(children),
+                None => text(StatusCode::NOT_FOUND, "path not found\n"),
+            }
+        }
+        "/api/search" => {
+            let pattern = query.get("q").map(String::as_str).unwrap_or_default();
+            let limit = query.get("limit").and_then(|limit| limit.parse().ok());
+            json_response(search(state, pattern, limit.unwrap_or(SEARCH_LIMIT)))
+        }
+        "/api/events" => events(state),
+        "/" | "/index.html" if state.web => Response::builder()
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(Full::new(Bytes::from(INDEX)).boxed())
+            .unwrap(),
+        _ => text(StatusCode::NOT_FOUND, "not found\n"),
+    }
+}
+
+/// Gets the progress of the scan.
+fn status(state: &State) -> Value {
+    let finished = *state.finished.lock().unwrap();
+    let tree = state.tree.lock().unwrap();
+    let elapsed = tree.begin.map(|b| b.elapsed().as_secs_f32()).unwrap_or(1.);
+    let root = tree.paths.get(&None);
+    json!({
+        "finished": finished,
+        "elapsed": elapsed,
+        "totalFiles": tree.total_files,
+        "progressFiles": tree.in_process_files,
+        "events": tree.event_cnt,
+        "speed": tree.process_events as f32 / elapsed,
+        "size": root.and_then(|root| root.size).unwrap_or(0),
+        "files": root.map_or(0, |root| root.files),
+    })
+}
+
+/// Gets the node and its children sorted by size, from the largest.
+fn children(state: &State, id: Option<PathId>) -> Option<Value> {
+    let tree = state.tree.lock().unwrap();
+    let node = tree.paths.get(&id)?;
+    let size = node.size.unwrap_or(0);
+    // The ratios to all the roots, which are known after the roots finish.
+    let root_size = tree.paths.get(&None).and_then(|root| root.size);
+    let all_ratio = |size: u64| Some(size).zip(root_size).to_ratio();
+
+    let mut children = node
+        .children
+        .iter()
+        .filter_map(|child| Some((*child, tree.paths.get(&Some(*child))?)))
+        .collect::<Vec<_>>();
+    children.sort_by_key(|(_, child)| std::cmp::Reverse(child.size.unwrap_or(0)));
+    let children = children
+        .into_iter()
+        .map(|(child_id, child)| {
+            let path = state.paths.get_path(child_id);
+            // The roots are named by their full paths.
+            let name = path.as_deref().map(|path| match path.file_name() {
+                Some(name) if id.is_some() => display_path(name.as_ref()).into_owned(),
+                _ => display_path(path).into_owned(),
+            });
+            let child_size = child.size.unwrap_or(0);
+            json!({
+                "id": child_id.into_raw(),
+                "name": name,
+                "path": path.as_deref().map(display_path),
+                "size": child_size,
+                "files": child.files,
+                "isFile": child.is_file,
+                "ratio": Some((child_size, size)).to_ratio(),
+                "allRatio": all_ratio(child_size),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let path = id.and_then(|id| state.paths.get_path(id));
+    Some(json!({
+        "id": id.map(PathId::into_raw),
+        "path": path.as_deref().map(display_path),
+        "parent": node.parent.map(PathId::into_raw),
+        "size": size,
+        "allRatio": all_ratio(size),
+        "files": node.files,
+        "isFile": node.is_file,
+        "children": children,
+    }))
+}
+
+/// Searches the paths containing the pattern, ignoring the case.
+fn search(state: &State, pattern: &str, limit: usize) -> Value {
+    // The paths are resolved after the tree is unlocked, to not block the
+    // scan for long.
+    let entries = {
+        let tree = state.tree.lock().unwrap();
+        tree.paths
+            .iter()
+            .filter_map(|(id, node)| Some((*id)?).zip(Some((node.size.unwrap_or(0), node.is_file))))
+            .collect::<Vec<_>>()
+    };
+
+    let pattern = pattern.to_lowercase();
+    let mut matches = entries
+        .into_iter()
+        .filter_map(|(id, (size, is_file))| {
+            let path = display_path(&state.paths.get_path(id)?).into_owned();
+            path.to_lowercase()
+                .contains(&pattern)
+                .then_some((id, path, size, is_file))
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.1.cmp(&b.1)));
+    matches.truncate(limit);
+
+    let matches = matches
+        .into_iter()
+        .map(|(id, path, size, is_file)| {
+            json!({ "id": id.into_raw(), "path": path, "size": size, "isFile": is_file })
+        })
+        .collect::<Vec<_>>();
+    json!(matches)
+}
+
+/// Streams the events as server-sent events. The events before subscribing
+/// are not replayed, and an `end` event is sent after the scan finishes.
+fn events(state: &State) -> Response<Body> {
+    let events = state.events.lock().unwrap();
+    let mut events = events.as_ref().map(broadcast::Sender::subscribe);
+    let paths = state.paths.clone();
+
+    let (mut sender, body) = Channel::<Bytes, Infallible>::new(16);
+    tokio::spawn(async move {
+        let mut encoder = StreamEncoder::default();
+        let mut records = vec![StreamRecord::header()];
+        loop {
+            for record in records.drain(..) {
+                let record = serde_json::to_string(&record).unwrap();
+                let data = Bytes::from(format!("data: {record}\n\n"));
+                if sender.send_data(data).await.is_err() {
+                    return;
+                }
+            }
+            let event = match &mut events {
+                Some(events) => events.recv().await,
+                None => Err(RecvError::Closed),
+            };
+            let data = match event {
+                Ok(event) => {
+                    encoder.encode(event, |id| paths.get_path(id), &mut records);
+                    continue;
+                }
+                Err(RecvError::Lagged(skipped)) => format!("event: lagged\ndata: {skipped}\n\n"),
+                Err(RecvError::Closed) => "event: end\ndata:\n\n".to_owned(),
+            };
+            let end = data.starts_with("event: end");
+            if sender.send_data(Bytes::from(data)).await.is_err() || end {
+                return;
+            }
+        }
+    });
+
+    Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(body.boxed())
+        .unwrap()
+}
+
+/// Responds with the JSON value.
+fn json_response(value: Value) -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(value.to_string())).boxed())
+        .unwrap()
+}
+
+/// Responds with the plain text.
+fn text(status: StatusCode, text: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(Full::new(Bytes::from(text)).boxed())
+        .unwrap()
+}
+
+/// Parses the `application/x-www-form-urlencoded` query string.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+/// Decodes the percent-encoded component, where `+` is a space.
+fn decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = rest.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(decoded) => {
+                        bytes.push(decoded);
+                        rest = &rest[2..];
+                    }
+                    None => bytes.push(b'%'),
+                }
+            }
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -1,3 +1,3 @@
-#![allow(missing_docs)]
+#![allow(missing_docs, dead_code)]
 
 slint::include_modules!();
@@ -0,0 +1,47 @@
+//! Streams the scans to the remote clients, see [`shr::serve_agent`].
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use shr::Shr;
+use tokio::sync::Semaphore;
+
+use crate::args::AgentArgs;
+
+/// Listens on the address, and streams a fresh scan to each client. The clients
+/// beyond `--max-scans` scans at once are refused, so that they cannot start
+/// any number of whole-tree scans.
+pub async fn run(args: AgentArgs) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .with_context(|| format!("failed to listen on {}", args.listen))?;
+    eprintln!("agent listening on {}", args.listen);
+
+    let scans = Arc::new(Semaphore::new(args.max_scans));
+    let args = Arc::new(args);
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                eprintln!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+        // The stream is dropped, which closes the connection.
+        let Ok(permit) = scans.clone().try_acquire_owned() else {
+            eprintln!("refused {peer}: {} scan(s) are running", args.max_scans);
+            continue;
+        };
+        eprintln!("scanning for {peer}");
+        let args = args.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let shr = Shr::from_roots(args.dirs.clone()).with_owners(args.by_owner);
+            let mut rx = args.scan.apply(shr).run().await;
+            match shr::serve_agent(&mut rx, stream).await {
+                Ok(()) => eprintln!("finished streaming to {peer}"),
+                Err(e) => eprintln!("failed to stream to {peer}: {e:#}"),
+            }
+        });
+    }
+}
@@ -1,39 +1,557 @@
 use core::fmt;
-use std::path::PathBuf;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
+use anyhow::Context;
 use clap::Parser;
 
 #[derive(Debug, Parser)]
 #[command(name = "shr", version, about)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
-    /// The directory to scan.
-    #[clap()]
-    dir: PathBuf,
+    /// The subcommand to run, which defaults to reporting the disk usage.
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    /// The output format.
-    #[clap(long, default_value_t = Format::Du)]
-    format: Format,
+    /// The arguments to report the disk usage.
+    #[clap(flatten)]
+    pub scan: ScanArgs,
 }
 
 impl Args {
-    /// Builds the `shr` instance.
-    pub async fn build(self) -> (shr::ShrRx, Format) {
-        let rx = shr::shr(self.dir).await;
-        (rx, self.format)
+    /// Gets the options of the scan of the command.
+    fn scan_options(&mut self) -> &mut ScanOptions {
+        match &mut self.command {
+            Some(Command::Dupes(args)) => &mut args.scan,
+            Some(Command::ServeMetrics(args)) => &mut args.scan,
+            Some(Command::Agent(args)) => &mut args.scan,
+            Some(Command::Check(args)) => &mut args.scan,
+            None => &mut self.scan.scan,
+        }
+    }
+
+    /// Gets the profile of the config files to apply.
+    pub fn profile(&mut self) -> Option<&str> {
+        self.scan_options().profile.as_deref()
+    }
+
+    /// Fills the options of the command not given by the flags or the
+    /// environment variables from the config, see [`shr::Config`].
+    pub fn apply_config(&mut self, config: &shr::Config) -> anyhow::Result<()> {
+        self.scan_options().apply_config(config);
+        let units = match &config.units {
+            Some(units) => Some(from_config(units, "units")?),
+            None => None,
+        };
+        match &mut self.command {
+            Some(Command::Dupes(args)) => args.units = args.units.or(units),
+            Some(Command::Check(args)) => args.units = args.units.or(units),
+            Some(Command::ServeMetrics(_) | Command::Agent(_)) => {}
+            None => {
+                let args = &mut self.scan;
+                args.units = args.units.or(units);
+                if let (None, Some(format)) = (args.format, &config.format) {
+                    args.format = Some(from_config(format, "format")?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the combinations of the options which clap cannot, since the
+    /// format may come from the config.
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        if self.command.is_some() {
+            return Ok(());
+        }
+        let args = &self.scan;
+        let format = args.format.unwrap_or(Format::Du);
+        if args.largest.is_some() && !format.reports_largest() {
+            return Err(<Self as clap::CommandFactory>::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("--largest cannot be reported in the {format} format"),
+            ));
+        }
+        if args.older_than.is_some() && !format.filters_stale() {
+            return Err(<Self as clap::CommandFactory>::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("--older-than cannot be applied to the {format} format"),
+            ));
+        }
+        Ok(())
     }
 }
 
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Finds the duplicate files.
+    Dupes(DupesArgs),
+    /// Rescans the directories periodically and serves the OpenMetrics gauges
+    /// over HTTP.
+    ServeMetrics(ServeMetricsArgs),
+    /// Streams a scan to each client connecting to the address, e.g.
+    /// `shr-browser --connect`.
+    ///
+    /// The clients are not authenticated, so anyone who can reach the address
+    /// can start a scan and read the paths and sizes of the directories. It
+    /// listens on the loopback by default, and scans for at most
+    /// `--max-scans` clients at once.
+    Agent(AgentArgs),
+    /// Checks the size budgets.
+    ///
+    /// The limits are binary whatever `--units` displays, e.g. `20G` is 20 GiB,
+    /// which is printed as `21.5G` in the si units.
+    ///
+    /// It exits with 1 if any budget is exceeded, or 2 if the rules cannot be
+    /// checked, e.g. a rule is invalid, the target of a rule does not exist or
+    /// the config fails to load.
+    Check(CheckArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CheckArgs {
+    /// The rules to check, e.g. `target/ <= 20G`, `**/*.log <= 1G each` or
+    /// `files(/tmp) <= 1M`.
+    #[clap(required_unless_present = "rules_file")]
+    pub rules: Vec<String>,
+
+    /// Reads more rules from the file, one per line, where `#` begins a
+    /// comment.
+    #[clap(long, value_name = "FILE")]
+    pub rules_file: Option<PathBuf>,
+
+    /// The directory which the relative paths and globs of the rules are in.
+    #[clap(long, short = 'C', default_value = ".")]
+    pub dir: PathBuf,
+
+    /// Prints the results in JSON.
+    #[clap(long)]
+    pub json: bool,
+
+    /// The units of the sizes, which defaults to `si`.
+    #[clap(long, env = "SHR_UNITS")]
+    pub units: Option<Units>,
+
+    /// The options of the scan.
+    #[clap(flatten)]
+    pub scan: ScanOptions,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AgentArgs {
+    /// The directories to scan for each client.
+    #[clap(required = true)]
+    pub dirs: Vec<PathBuf>,
+
+    /// The address to listen on, e.g. `0.0.0.0:7878` to accept the remote
+    /// clients.
+    #[clap(long, default_value = "127.0.0.1:7878")]
+    pub listen: SocketAddr,
+
+    /// The maximum number of the scans at once. The clients connecting while
+    /// as many scans are running are refused.
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    pub max_scans: usize,
+
+    /// Breaks down the usage of each directory by owner (uid/gid).
+    #[clap(long)]
+    pub by_owner: bool,
+
+    /// The options of the scan.
+    #[clap(flatten)]
+    pub scan: ScanOptions,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DupesArgs {
+    /// The directories to scan.
+    #[clap(required = true)]
+    pub dirs: Vec<PathBuf>,
+
+    /// Prints the duplicate groups in JSON lines.
+    #[clap(long)]
+    pub json: bool,
+
+    /// Finds the identical directory trees instead of files.
+    #[clap(long)]
+    pub trees: bool,
+
+    /// The units of the sizes, which defaults to `si`.
+    #[clap(long, env = "SHR_UNITS")]
+    pub units: Option<Units>,
+
+    /// The options of the scan.
+    #[clap(flatten)]
+    pub scan: ScanOptions,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ServeMetricsArgs {
+    /// The directories to scan.
+    #[clap(required = true)]
+    pub dirs: Vec<PathBuf>,
+
+    /// The address to serve `/metrics` at.
+    #[clap(long, default_value = "127.0.0.1:9184")]
+    pub listen: SocketAddr,
+
+    /// The interval between the scans, e.g. `10m`.
+    #[clap(long, default_value = "5m", value_parser = humantime::parse_duration)]
+    pub interval: Duration,
+
+    /// Only reports the directories at most N levels below the roots, which
+    /// defaults to 2.
+    #[clap(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// Prunes the directories smaller than the size, e.g. `10M`.
+    #[clap(long, value_name = "SIZE", value_parser = shr::utils::parse_human_size)]
+    pub min_size: Option<u64>,
+
+    /// The options of the scan.
+    #[clap(flatten)]
+    pub scan: ScanOptions,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ScanArgs {
+    /// The directories to scan. The directories nested inside another one are
+    /// skipped.
+    #[clap(required_unless_present_any = ["import", "input", "stdin"])]
+    dirs: Vec<PathBuf>,
+
+    /// Loads the entries from an ncdu export instead of scanning.
+    #[clap(long, value_name = "FILE", conflicts_with = "dirs")]
+    import: Option<PathBuf>,
+
+    /// Reads a serialized event stream in JSON lines from the file instead of
+    /// scanning, e.g. the output of `shr --format json` or `--format stream`.
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["dirs", "import"])]
+    input: Option<PathBuf>,
+
+    /// Reads a serialized event stream from stdin instead of scanning, see
+    /// `--input`.
+    #[clap(long, conflicts_with_all = ["dirs", "import", "input"])]
+    stdin: bool,
+
+    /// The output format, which defaults to `du`.
+    #[clap(long, env = "SHR_FORMAT")]
+    format: Option<Format>,
+
+    /// The units of the sizes, which defaults to `si`.
+    #[clap(long, env = "SHR_UNITS")]
+    units: Option<Units>,
+
+    /// The chart to draw in the svg format.
+    #[clap(long, default_value_t = Chart::Treemap)]
+    chart: Chart,
+
+    /// Only reports the entries at most N levels below the roots.
+    #[clap(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Prunes the entries smaller than the size, e.g. `10M`, from the chart
+    /// reports. The folded stacks aggregate them into "(other)".
+    #[clap(long, value_name = "SIZE", value_parser = shr::utils::parse_human_size)]
+    min_size: Option<u64>,
+
+    /// Breaks down the usage of each directory by owner (uid/gid).
+    #[clap(long)]
+    by_owner: bool,
+
+    /// Breaks down the usage of each directory by age buckets.
+    #[clap(long)]
+    ages: bool,
+
+    /// The timestamp used to determine the age of files.
+    #[clap(long, default_value_t = AgeBy::Mtime)]
+    age_by: AgeBy,
+
+    /// Only reports the files and directories untouched for the duration,
+    /// e.g. `180d`, in the du, json, csv or tsv format.
+    #[clap(long, value_parser = humantime::parse_duration, conflicts_with = "largest")]
+    older_than: Option<Duration>,
+
+    /// Only reports the N largest files and leaf directories, in the du, json,
+    /// csv or tsv format.
+    #[clap(long, value_name = "N")]
+    largest: Option<usize>,
+
+    /// The options of the scan.
+    #[clap(flatten)]
+    scan: ScanOptions,
+}
+
+/// The options of a scan, shared by the subcommands which scan.
+#[derive(Debug, clap::Args)]
+pub struct ScanOptions {
+    /// Skips the entries matching the glob, e.g. `node_modules`, which matches
+    /// at any depth unless it begins with `/`.
+    #[clap(long, value_name = "GLOB", env = "SHR_EXCLUDE", value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Applies the named profile of the config files, e.g. `ci`.
+    #[clap(long, value_name = "NAME", env = "SHR_PROFILE")]
+    pub profile: Option<String>,
+
+    /// The number of threads to scan, which defaults to all the cores.
+    #[clap(long, env = "SHR_THREADS")]
+    pub threads: Option<usize>,
+
+    /// Rate-limits the metadata operations per second.
+    #[clap(long, value_name = "N")]
+    pub max_iops: Option<u32>,
+
+    /// Scans in idle I/O priority and low CPU priority (Linux only).
+    #[clap(long)]
+    pub gentle: bool,
+}
+
+impl ScanOptions {
+    /// Fills the options not given by the flags or the environment variables
+    /// from the config, and puts its excludes first.
+    fn apply_config(&mut self, config: &shr::Config) {
+        self.threads = self.threads.or(config.threads);
+        self.exclude.splice(0..0, config.exclude.iter().cloned());
+    }
+
+    /// Applies the options to the scan.
+    pub fn apply(&self, shr: shr::Shr) -> shr::Shr {
+        shr.with_threads(self.threads.unwrap_or(0))
+            .with_max_iops(self.max_iops)
+            .with_gentle(self.gentle)
+            .with_excludes(
+                self.exclude
+                    .iter()
+                    .map(|glob| shr::Glob::new(glob))
+                    .collect(),
+            )
+    }
+}
+
+impl ScanArgs {
+    /// Builds the `shr` instance, or loads the entries to import or read.
+    pub async fn build(self) -> anyhow::Result<(shr::ShrRx, Report)> {
+        let timestamp = self.age_by.into();
+        let with_ages = self.ages || self.older_than.is_some();
+
+        let roots = self.dirs.len().max(1);
+        let rx = match (&self.import, &self.input) {
+            (Some(file), _) => import(file, self.largest)?,
+            (_, Some(file)) => read(file, self.largest)?,
+            _ if self.stdin => {
+                shr::read_events(std::io::BufReader::new(std::io::stdin()), self.largest)?
+            }
+            _ => self.scan(timestamp, with_ages).await,
+        };
+
+        let now = std::time::SystemTime::now();
+        let report = Report {
+            format: self.format.unwrap_or(Format::Du),
+            units: self.units.unwrap_or_default(),
+            chart: self.chart,
+            roots,
+            largest: self.largest.is_some(),
+            max_depth: self.max_depth,
+            min_size: self.min_size,
+            ages: self.ages,
+            timestamp,
+            older_than: self
+                .older_than
+                .and_then(|d| now.checked_sub(d))
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+        };
+        Ok((rx, report))
+    }
+
+    async fn scan(&self, timestamp: shr::Timestamp, with_ages: bool) -> shr::ShrRx {
+        let shr = shr::Shr::from_roots(self.dirs.clone())
+            .with_max_depth(self.max_depth.unwrap_or(usize::MAX))
+            .with_owners(self.by_owner)
+            .with_ages(with_ages.then_some(timestamp))
+            .with_largest(self.largest);
+        self.scan.apply(shr).run().await
+    }
+}
+
+/// Parses a value of the config, e.g. the format.
+fn from_config<T: clap::ValueEnum>(value: &str, name: &str) -> anyhow::Result<T> {
+    T::from_str(value, true).map_err(|e| anyhow::anyhow!("invalid {name} in config: {e}"))
+}
+
+/// Loads the entries from an ncdu export.
+fn import(file: &Path, largest: Option<usize>) -> anyhow::Result<shr::ShrRx> {
+    let reader =
+        std::fs::File::open(file).with_context(|| format!("failed to open {}", file.display()))?;
+    shr::load_ncdu(std::io::BufReader::new(reader), largest)
+}
+
+/// Reads a serialized event stream from the file.
+fn read(file: &Path, largest: Option<usize>) -> anyhow::Result<shr::ShrRx> {
+    let reader =
+        std::fs::File::open(file).with_context(|| format!("failed to open {}", file.display()))?;
+    shr::read_events(std::io::BufReader::new(reader), largest)
+}
+
+/// The options to report the scan.
+pub struct Report {
+    /// The output format.
+    pub format: Format,
+    /// The units of the sizes.
+    pub units: Units,
+    /// The chart to draw in the svg format.
+    pub chart: Chart,
+    /// The number of the roots to scan.
+    pub roots: usize,
+    /// Whether to only report the largest files and leaf directories.
+    pub largest: bool,
+    /// The maximum depth of the entries to report.
+    pub max_depth: Option<usize>,
+    /// The minimum size of the entries in the chart reports.
+    pub min_size: Option<u64>,
+    /// Whether to print the age buckets.
+    pub ages: bool,
+    /// The timestamp used to determine the age of files.
+    pub timestamp: shr::Timestamp,
+    /// Only reports the entries not touched since the time, in seconds since
+    /// the unix epoch.
+    pub older_than: Option<u64>,
+}
+
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
 pub enum Format {
+    /// JSON lines of the events with full paths.
     Json,
+    /// Compact JSON lines defining each path once and referencing the ids.
+    Stream,
+    /// The compact JSON lines stamped with the milliseconds since the scan
+    /// began, which can be replayed by `shr-browser --replay`.
+    Log,
+    /// Human-readable lines like `du`.
     Du,
+    /// The JSON export of ncdu, which can be loaded by `ncdu -f`.
+    Ncdu,
+    /// Comma-separated values, one row per entry.
+    Csv,
+    /// Tab-separated values, one row per entry.
+    Tsv,
+    /// A self-contained HTML page with a zoomable treemap, 4 levels deep and
+    /// without the entries under 0.1% of the total unless `--max-depth` or
+    /// `--min-size` is given.
+    Html,
+    /// A static SVG chart, drawn as `--chart`.
+    Svg,
+    /// Folded stacks in bytes for flame graphs, e.g. `dir;subdir;file 1024`.
+    Folded,
+    /// OpenMetrics gauges of the directories, 2 levels deep unless
+    /// `--max-depth` is given.
+    Openmetrics,
+}
+
+impl Format {
+    /// Whether the largest entries can be reported in the format.
+    fn reports_largest(self) -> bool {
+        matches!(self, Format::Du | Format::Json | Format::Csv | Format::Tsv)
+    }
+
+    /// Whether the stale entries can be filtered by `--older-than` in the
+    /// format, which reports each entry on its own.
+    fn filters_stale(self) -> bool {
+        matches!(self, Format::Du | Format::Json | Format::Csv | Format::Tsv)
+    }
 }
 
 impl fmt::Display for Format {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Format::Json => write!(f, "json"),
+            Format::Stream => write!(f, "stream"),
+            Format::Log => write!(f, "log"),
             Format::Du => write!(f, "du"),
+            Format::Ncdu => write!(f, "ncdu"),
+            Format::Csv => write!(f, "csv"),
+            Format::Tsv => write!(f, "tsv"),
+            Format::Html => write!(f, "html"),
+            Format::Svg => write!(f, "svg"),
+            Format::Folded => write!(f, "folded"),
+            Format::Openmetrics => write!(f, "openmetrics"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum Units {
+    /// Powers of 1000, e.g. `1.5M`.
+    #[default]
+    Si,
+    /// Powers of 1024, e.g. `1.4M`.
+    Binary,
+    /// The bytes, e.g. `1500000B`.
+    Bytes,
+}
+
+impl Units {
+    /// Gets the format of [`shr::utils::human_readable_number`].
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Units::Si => "si",
+            Units::Binary => "",
+            Units::Bytes => "b",
+        }
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Units::Si => write!(f, "si"),
+            Units::Binary => write!(f, "binary"),
+            Units::Bytes => write!(f, "bytes"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Chart {
+    /// Nested rectangles in proportion to the sizes.
+    Treemap,
+    /// Rings of the levels around the root, in proportion to the sizes.
+    Sunburst,
+}
+
+impl fmt::Display for Chart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chart::Treemap => write!(f, "treemap"),
+            Chart::Sunburst => write!(f, "sunburst"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum AgeBy {
+    Mtime,
+    Atime,
+}
+
+impl fmt::Display for AgeBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgeBy::Mtime => write!(f, "mtime"),
+            AgeBy::Atime => write!(f, "atime"),
+        }
+    }
+}
+
+impl From<AgeBy> for shr::Timestamp {
+    fn from(by: AgeBy) -> Self {
+        match by {
+            AgeBy::Mtime => shr::Timestamp::Modified,
+            AgeBy::Atime => shr::Timestamp::Accessed,
         }
     }
 }
@@ -0,0 +1,380 @@
+//! Checks the size budgets, e.g. `target/ <= 20G`.
+//!
+//! A rule is `TARGET <= LIMIT [each]`, where the target is a path, a glob
+//! matching the entries (see [`shr::Glob`]), or either in `files(...)` to
+//! limit the number of files instead of the size. The entries matched by a
+//! glob are limited in total, or one by one if `each` is given. The units of
+//! the limits are binary for both, e.g. `1K` is 1024 bytes or files, whatever
+//! units the results are displayed in, so the resolved limits are displayed
+//! as well.
+
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+use serde::Serialize;
+use shr::{Glob, PathId};
+
+use crate::{
+    args::{CheckArgs, Units},
+    tree::{Node, Tree},
+};
+
+/// A budget to check.
+struct Rule {
+    /// The rule as given.
+    text: String,
+    /// The entries to limit.
+    target: Target,
+    /// Whether to limit the number of files instead of the size.
+    files: bool,
+    /// The maximum size in bytes, or the maximum number of files.
+    limit: u64,
+    /// Whether to limit each entry instead of the total.
+    each: bool,
+}
+
+/// The entries to limit.
+enum Target {
+    /// The entry at the path.
+    Path(PathBuf),
+    /// The entries matching the glob.
+    Glob(Glob),
+}
+
+/// The result of a rule.
+#[derive(Serialize)]
+struct Outcome<'a> {
+    /// The rule as given.
+    rule: &'a str,
+    /// The measure limited, either `size` or `files`.
+    measure: &'static str,
+    /// The limit of the measure.
+    limit: u64,
+    /// Whether each entry is limited instead of the total.
+    each: bool,
+    /// The total measure of the entries matched.
+    actual: u64,
+    /// The measure of the largest entry matched.
+    largest: u64,
+    /// The number of the entries matched.
+    entries: usize,
+    /// Whether the rule holds.
+    passed: bool,
+    /// The entries exceeding the limit, or the target if the total does.
+    violations: Vec<Violation>,
+}
+
+/// An entry exceeding the limit.
+#[derive(Serialize)]
+struct Violation {
+    /// The path to the entry, or the target of the rule.
+    path: String,
+    /// The measure of the entry.
+    actual: u64,
+}
+
+/// Scans the targets of the rules, and reports the results. It returns
+/// whether all the rules hold.
+pub async fn run(args: CheckArgs, w: &mut impl Write) -> anyhow::Result<bool> {
+    let mut texts = args.rules.clone();
+    if let Some(file) = &args.rules_file {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("failed to read {}", file.display()))?;
+        texts.extend(
+            content
+                .lines()
+                .map(|line| line.split_once('#').map_or(line, |(rule, _)| rule).trim())
+                .filter(|line| !line.is_empty())
+                .map(str::to_owned),
+        );
+    }
+    let rules = texts
+        .iter()
+        .map(|text| parse_rule(text))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // The roots are absolute, since a root covering another one is scanned
+    // instead of it, whose paths must be comparable.
+    let cwd = std::env::current_dir().context("failed to get current directory")?;
+    let dir = normalize(&cwd.join(&args.dir));
+    let mut roots = rules
+        .iter()
+        .map(|rule| match &rule.target {
+            Target::Path(path) => normalize(&dir.join(path)),
+            Target::Glob(glob) => normalize(&dir.join(glob.base())),
+        })
+        .collect::<Vec<_>>();
+    roots.sort();
+    roots.dedup();
+    let mut rx = args.scan.apply(shr::Shr::from_roots(roots)).run().await;
+    let tree = Tree::collect(&mut rx).await;
+
+    let outcomes = rules
+        .iter()
+        .map(|rule| check(&tree, &dir, &cwd, rule))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let passed = outcomes.iter().all(|outcome| outcome.passed);
+
+    if args.json {
+        let results = serde_json::json!({ "passed": passed, "rules": outcomes });
+        serde_json::to_writer(&mut *w, &results).context("failed to serialize results")?;
+        w.write_all(b"\n").context("failed to write newline")?;
+        return Ok(passed);
+    }
+
+    let units = args.units.unwrap_or_default();
+    for (rule, outcome) in rules.iter().zip(&outcomes) {
+        // The limit is resolved, since the binary units of the rules may
+        // differ from the units displayed.
+        let text = format!(
+            "{} (limit {})",
+            outcome.rule,
+            amount(rule, units, rule.limit)
+        );
+        if outcome.passed && rule.each {
+            let largest = amount(rule, units, outcome.largest);
+            let entries = outcome.entries;
+            writeln!(w, "ok   {text}: {entries} entries, the largest {largest}")?;
+        } else if outcome.passed {
+            let actual = amount(rule, units, outcome.actual);
+            writeln!(w, "ok   {text}: {actual}")?;
+        }
+        for violation in &outcome.violations {
+            let actual = amount(rule, units, violation.actual);
+            writeln!(w, "FAIL {text}: {} ({actual})", violation.path)?;
+        }
+    }
+    let failed = outcomes.iter().filter(|outcome| !outcome.passed).count();
+    match failed {
+        0 => writeln!(w, "all {} rule(s) passed", outcomes.len())?,
+        _ => writeln!(w, "{failed} of {} rule(s) violated", outcomes.len())?,
+    }
+    Ok(passed)
+}
+
+/// Parses a rule like `target/ <= 20G`.
+fn parse_rule(text: &str) -> anyhow::Result<Rule> {
+    let Some((target, limit)) = text.split_once("<=") else {
+        bail!("invalid rule, expected `TARGET <= LIMIT [each]`: {text}");
+    };
+    let (limit, each) = match limit.trim().strip_suffix("each") {
+        Some(limit) => (limit.trim(), true),
+        None => (limit.trim(), false),
+    };
+    let target = target.trim();
+    let (target, files) = match target
+        .strip_prefix("files(")
+        .and_then(|target| target.strip_suffix(')'))
+    {
+        Some(target) => (target.trim(), true),
+        None => (target, false),
+    };
+    if target.is_empty() {
+        bail!("invalid rule, the target is empty: {text}");
+    }
+
+    let limit = if files {
+        parse_count(limit)
+    } else {
+        shr::utils::parse_human_size(limit)
+    };
+    let limit = limit.map_err(|e| anyhow::anyhow!("invalid rule `{text}`: {e}"))?;
+    let target = match Glob::is_glob(target) {
+        true => Target::Glob(Glob::new(target)),
+        false => Target::Path(PathBuf::from(target)),
+    };
+    Ok(Rule {
+        text: text.trim().to_owned(),
+        target,
+        files,
+        limit,
+        each,
+    })
+}
+
+/// Parses a number of files like `500` or `1M`, whose units are binary as the
+/// sizes.
+fn parse_count(s: &str) -> Result<u64, String> {
+    if s.trim().ends_with(['B', 'b']) {
+        return Err(format!("invalid count unit: {s}"));
+    }
+    shr::utils::parse_human_size(s).map_err(|_| format!("invalid count: {s}"))
+}
+
+/// Checks a rule against the scanned entries, whose paths are displayed
+/// relative to `cwd`. The target path of a rule must exist, so that a typo
+/// does not disable the budget.
+fn check<'a>(tree: &Tree, dir: &Path, cwd: &Path, rule: &'a Rule) -> anyhow::Result<Outcome<'a>> {
+    let matched = match &rule.target {
+        Target::Path(path) => {
+            let path = normalize(&dir.join(path));
+            let matched = tree
+                .nodes
+                .iter()
+                .filter(|(_, node)| *node.path == *path)
+                .map(|(id, _)| *id)
+                .collect::<HashSet<_>>();
+            if matched.is_empty() {
+                bail!(
+                    "the target of `{}` does not exist: {}",
+                    rule.text,
+                    shr::display_path(&path)
+                );
+            }
+            matched
+        }
+        Target::Glob(glob) => tree
+            .nodes
+            .iter()
+            .filter(|(_, node)| {
+                // The absolute globs are matched against the full paths.
+                let path = match glob.as_str().starts_with('/') {
+                    true => &*node.path,
+                    false => node.path.strip_prefix(dir).unwrap_or(&node.path),
+                };
+                glob.matches(path)
+            })
+            .map(|(id, _)| *id)
+            .collect(),
+    };
+    let value = |node: &Node| match rule.files {
+        true => node.num_files as u64,
+        false => node.size,
+    };
+
+    // The entries inside another matched one are not counted twice.
+    let outermost = matched
+        .iter()
+        .filter(|id| !has_matched_ancestor(tree, **id, &matched))
+        .map(|id| tree.node(*id))
+        .collect::<Vec<_>>();
+    let actual = outermost.iter().map(|node| value(node)).sum::<u64>();
+    let largest = matched
+        .iter()
+        .map(|id| value(tree.node(*id)))
+        .max()
+        .unwrap_or(0);
+
+    let mut violations = if rule.each {
+        let mut exceeded = matched
+            .iter()
+            .map(|id| tree.node(*id))
+            .filter(|node| value(node) > rule.limit)
+            .map(|node| (show(&node.path, cwd).into_owned(), value(node)))
+            .collect::<Vec<_>>();
+        exceeded.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        exceeded
+    } else if actual > rule.limit {
+        let target = match &rule.target {
+            Target::Path(path) => shr::display_path(path).into_owned(),
+            Target::Glob(glob) => glob.as_str().to_owned(),
+        };
+        vec![(target, actual)]
+    } else {
+        Vec::new()
+    };
+
+    Ok(Outcome {
+        rule: &rule.text,
+        measure: if rule.files { "files" } else { "size" },
+        limit: rule.limit,
+        each: rule.each,
+        actual,
+        largest,
+        entries: matched.len(),
+        passed: violations.is_empty(),
+        violations: violations
+            .drain(..)
+            .map(|(path, actual)| Violation { path, actual })
+            .collect(),
+    })
+}
+
+/// Whether any ancestor of the entry is matched.
+fn has_matched_ancestor(tree: &Tree, id: PathId, matched: &HashSet<PathId>) -> bool {
+    let mut parent = tree.node(id).parent;
+    while let Some(id) = parent {
+        if matched.contains(&id) {
+            return true;
+        }
+        parent = tree.node(id).parent;
+    }
+    false
+}
+
+/// Removes the `.` components and the trailing `/`, e.g. `./target/`.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != std::path::Component::CurDir)
+        .collect()
+}
+
+/// Displays the path relative to the current directory if it is under it.
+fn show<'a>(path: &'a Path, cwd: &Path) -> Cow<'a, str> {
+    let path = path.strip_prefix(cwd).unwrap_or(path);
+    match path.as_os_str().is_empty() {
+        true => Cow::Borrowed("."),
+        false => shr::display_path(path),
+    }
+}
+
+/// Displays the size, or the number of files if the rule limits it.
+fn amount(rule: &Rule, units: Units, value: u64) -> String {
+    match rule.files {
+        true => format!("{value} file(s)"),
+        false => shr::utils::human_readable_number(value, units.as_str()).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rules() {
+        let rule = parse_rule(" target/ <= 20G ").unwrap();
+        assert_eq!(rule.text, "target/ <= 20G");
+        assert!(matches!(&rule.target, Target::Path(path) if path == Path::new("target/")));
+        assert_eq!(rule.limit, 20 << 30);
+        assert!(!rule.files && !rule.each);
+
+        let rule = parse_rule("**/*.log <= 1.5M each").unwrap();
+        assert!(matches!(&rule.target, Target::Glob(glob) if glob.as_str() == "**/*.log"));
+        assert_eq!(rule.limit, 3 << 19);
+        assert!(rule.each);
+
+        let rule = parse_rule("files( /tmp ) <= 10k").unwrap();
+        assert!(matches!(&rule.target, Target::Path(path) if path == Path::new("/tmp")));
+        assert!(rule.files);
+        assert_eq!(rule.limit, 10 << 10);
+    }
+
+    #[test]
+    fn parse_invalid_rules() {
+        for rule in [
+            "target/",
+            "target/ < 20G",
+            " <= 20G",
+            "files() <= 1",
+            "target/ <= 20X",
+            "target/ <=",
+            "files(/tmp) <= 1KB",
+        ] {
+            assert!(parse_rule(rule).is_err(), "{rule}");
+        }
+    }
+
+    #[test]
+    fn count_and_size_units_agree() {
+        let count = parse_rule("files(a) <= 10k").unwrap().limit;
+        let size = parse_rule("a <= 10k").unwrap().limit;
+        assert_eq!(count, size);
+        assert_eq!(parse_count("500"), Ok(500));
+        assert_eq!(parse_count("1M"), Ok(1 << 20));
+    }
+}
@@ -0,0 +1,206 @@
+//! Reports the scan in CSV or TSV, one row per reported entry.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use shr::{Event, PathId, ShrRx};
+
+use crate::args::Report;
+
+/// The header of the table.
+const HEADER: [&str; 6] = ["path", "depth", "kind", "size", "files", "parent"];
+
+/// The separator of the fields.
+#[derive(Clone, Copy)]
+pub enum Separator {
+    /// Comma-separated values, quoted as RFC 4180.
+    Comma,
+    /// Tab-separated values, escaping the tabs, newlines and backslashes.
+    Tab,
+}
+
+/// Reports the entries as they are finished. The sizes are apparent sizes in
+/// bytes.
+pub async fn report(
+    rx: &mut ShrRx,
+    report: &Report,
+    sep: Separator,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    write_row(w, sep, HEADER.map(Cow::Borrowed))?;
+
+    // The depths and the parents of the directories not finished yet, which
+    // are dropped once they are, so that the memory does not grow with the
+    // number of the entries.
+    let mut dirs = HashMap::<PathId, (usize, Option<PathId>)>::new();
+    while let Some(event) = rx.recv().await {
+        let stale = report.is_stale(event.as_raw());
+        let (path, kind, size, num_files, depth, parent) = match event.to_raw() {
+            Event::Dir { path, parent } => {
+                let depth = parent.and_then(|p| dirs.get(&p)).map_or(0, |d| d.0 + 1);
+                dirs.insert(path, (depth, parent));
+                continue;
+            }
+            Event::FileFinish {
+                path, parent, size, ..
+            } => {
+                let depth = parent.and_then(|p| dirs.get(&p)).map_or(0, |d| d.0 + 1);
+                (path, "file", size, 1, depth, parent)
+            }
+            Event::DirFinish {
+                path,
+                size,
+                num_files,
+                ..
+            } => {
+                let (depth, parent) = dirs.remove(&path).unwrap_or_default();
+                (path, "dir", size, num_files, depth, parent)
+            }
+            Event::Total { .. } => continue,
+        };
+
+        if !stale || report.max_depth.is_some_and(|max| depth > max) {
+            continue;
+        }
+        let display = |id| rx.get_path(id).map(|p| shr::display_path(&p).into_owned());
+        let parent = parent.and_then(display);
+        let row = [
+            display(path).unwrap_or_default().into(),
+            depth.to_string().into(),
+            kind.into(),
+            size.to_string().into(),
+            num_files.to_string().into(),
+            parent.unwrap_or_default().into(),
+        ];
+        write_row(w, sep, row)?;
+    }
+    Ok(())
+}
+
+/// Writes a row of the table.
+pub fn write_row<const N: usize>(
+    w: &mut impl Write,
+    sep: Separator,
+    fields: [Cow<'_, str>; N],
+) -> io::Result<()> {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            w.write_all(match sep {
+                Separator::Comma => b",",
+                Separator::Tab => b"\t",
+            })?;
+        }
+        match sep {
+            Separator::Comma => write_csv_field(w, field)?,
+            Separator::Tab => write_tsv_field(w, field)?,
+        }
+    }
+    w.write_all(b"\n")
+}
+
+/// Quotes the field if it contains commas, quotes or line breaks.
+fn write_csv_field(w: &mut impl Write, field: &str) -> io::Result<()> {
+    if !field.contains([',', '"', '\n', '\r']) {
+        return w.write_all(field.as_bytes());
+    }
+    write!(w, "\"{}\"", field.replace('"', "\"\""))
+}
+
+/// Escapes the tabs, line breaks and backslashes, which cannot be quoted in
+/// TSV.
+fn write_tsv_field(w: &mut impl Write, field: &str) -> io::Result<()> {
+    if !field.contains(['\t', '\n', '\r', '\\']) {
+        return w.write_all(field.as_bytes());
+    }
+    for c in field.chars() {
+        match c {
+            '\t' => w.write_all(b"\\t")?,
+            '\n' => w.write_all(b"\\n")?,
+            '\r' => w.write_all(b"\\r")?,
+            '\\' => w.write_all(b"\\\\")?,
+            c => write!(w, "{c}")?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(sep: Separator, fields: [&str; 2]) -> String {
+        let mut w = Vec::new();
+        write_row(&mut w, sep, fields.map(Cow::Borrowed)).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+
+    #[tokio::test]
+    async fn report_rows() {
+        let export = concat!(
+            r#"[1,2,{},[{"name":"/r"},"#,
+            r#"[{"name":"a"},[{"name":"b"},{"name":"f","asize":2}]],"#,
+            r#"{"name":"g","asize":1}]]"#
+        );
+        let mut rx = shr::load_ncdu(export.as_bytes(), None).unwrap();
+        let report = Report {
+            format: crate::args::Format::Csv,
+            units: Default::default(),
+            chart: crate::args::Chart::Treemap,
+            roots: 1,
+            largest: false,
+            max_depth: Some(2),
+            min_size: None,
+            ages: false,
+            timestamp: Default::default(),
+            older_than: None,
+        };
+        let mut w = Vec::new();
+        super::report(&mut rx, &report, Separator::Comma, &mut w)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "path,depth,kind,size,files,parent\n\
+             /r/a/b,2,dir,2,1,/r/a\n\
+             /r/a,1,dir,2,1,/r\n\
+             /r/g,1,file,1,1,/r\n\
+             /r,0,dir,3,2,\n"
+        );
+    }
+
+    #[test]
+    fn quote_csv() {
+        let sep = Separator::Comma;
+        assert_eq!(row(sep, ["a b", "1"]), "a b,1\n");
+        assert_eq!(row(sep, ["a,b", "1"]), "\"a,b\",1\n");
+        assert_eq!(row(sep, ["a \"b\"", "1"]), "\"a \"\"b\"\"\",1\n");
+        assert_eq!(row(sep, ["a\nb", "1"]), "\"a\nb\",1\n");
+        assert_eq!(row(sep, ["a\r\nb", "1"]), "\"a\r\nb\",1\n");
+        // The tabs need no quotes in CSV.
+        assert_eq!(row(sep, ["a\tb", "1"]), "a\tb,1\n");
+    }
+
+    #[test]
+    fn escape_tsv() {
+        let sep = Separator::Tab;
+        assert_eq!(row(sep, ["a,\"b\"", "1"]), "a,\"b\"\t1\n");
+        assert_eq!(row(sep, ["a\tb", "1"]), "a\\tb\t1\n");
+        assert_eq!(row(sep, ["a\nb\r", "1"]), "a\\nb\\r\t1\n");
+        assert_eq!(row(sep, ["a\\tb", "1"]), "a\\\\tb\t1\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+        let path = Path::new(OsStr::from_bytes(b"a\xFF,b"));
+        let name = shr::display_path(path);
+        assert_eq!(row(Separator::Comma, [&name, "1"]), "\"a\\xFF,b\",1\n");
+        // The escaped backslash keeps the name apart from a literal `\xFF`.
+        assert_eq!(row(Separator::Tab, [&name, "1"]), "a\\\\xFF,b\t1\n");
+    }
+}
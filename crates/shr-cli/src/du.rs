@@ -0,0 +1,166 @@
+//! Reports the scan in a `du`-like format.
+
+use std::io::{self, Write};
+
+use shr::{AgeBucket, DirMeta, Event, EventDisplay, FileMeta, ImmutPath, OwnerNames, ShrRx};
+
+use crate::args::Report;
+
+/// Reports the entries as they are finished.
+pub async fn report(rx: &mut ShrRx, report: &Report, w: &mut impl Write) -> io::Result<()> {
+    let names = OwnerNames::load();
+    loop {
+        let event = rx.recv().await.map(|event| event.display());
+        match event {
+            Some(EventDisplay::DirFinish {
+                path,
+                size,
+                num_files,
+                meta,
+            }) => {
+                if !report.is_dir_stale(meta.as_deref()) {
+                    continue;
+                }
+                let reported = path.is_some();
+                report_entry(w, report, path, size, num_files)?;
+                if let Some(meta) = meta.filter(|_| reported) {
+                    report_owners(w, report, &meta, &names)?;
+                    if report.ages {
+                        report_ages(w, report, &meta)?;
+                    }
+                }
+            }
+            Some(EventDisplay::FileFinish {
+                path,
+                size,
+                parent: _,
+                meta,
+            }) => {
+                if !report.is_file_stale(meta.as_deref()) {
+                    continue;
+                }
+                report_entry(w, report, path, size, 0)?;
+            }
+            Some(EventDisplay::Total {
+                size,
+                num_files,
+                meta,
+            }) => {
+                if report.roots < 2 {
+                    continue;
+                }
+                let size = report.size(size);
+                writeln!(w, "total {size}, {num_files} file(s)")?;
+                if let Some(meta) = meta {
+                    report_owners(w, report, &meta, &names)?;
+                    if report.ages {
+                        report_ages(w, report, &meta)?;
+                    }
+                }
+            }
+            Some(EventDisplay::Dir { .. }) => {}
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+impl Report {
+    /// Displays the size in the units.
+    pub fn size(&self, size: u64) -> shr::utils::Hr<'static> {
+        shr::utils::human_readable_number(size, self.units.as_str())
+    }
+
+    /// Checks whether the file is untouched since `--older-than`.
+    pub fn is_file_stale(&self, meta: Option<&FileMeta>) -> bool {
+        let Some(cutoff) = self.older_than else {
+            return true;
+        };
+        let time = meta.and_then(|meta| self.timestamp.of(meta));
+        time.is_some_and(|time| time <= cutoff)
+    }
+
+    /// Checks whether nothing in the directory is touched since
+    /// `--older-than`.
+    pub fn is_dir_stale(&self, meta: Option<&DirMeta>) -> bool {
+        let Some(cutoff) = self.older_than else {
+            return true;
+        };
+        let newest = meta.and_then(|meta| meta.ages?.newest);
+        newest.is_some_and(|time| time <= cutoff)
+    }
+
+    /// Checks whether the finished file or directory is stale. The other
+    /// events are always kept.
+    pub fn is_stale(&self, event: &Event) -> bool {
+        match event {
+            Event::FileFinish { meta, .. } => self.is_file_stale(meta.as_deref()),
+            Event::DirFinish { meta, .. } => self.is_dir_stale(meta.as_deref()),
+            Event::Dir { .. } | Event::Total { .. } => true,
+        }
+    }
+}
+
+fn report_entry(
+    w: &mut impl Write,
+    report: &Report,
+    path: Option<ImmutPath>,
+    size: u64,
+    num_files: usize,
+) -> io::Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    let size = report.size(size);
+    let path = path.display();
+    if num_files > 0 {
+        writeln!(w, "{path} {size}, {num_files} file(s)")?;
+    } else {
+        writeln!(w, "{path} {size}")?;
+    }
+    Ok(())
+}
+
+fn report_owners(
+    w: &mut impl Write,
+    report: &Report,
+    meta: &DirMeta,
+    names: &OwnerNames,
+) -> io::Result<()> {
+    let Some(owners) = &meta.owners else {
+        return Ok(());
+    };
+
+    let mut users = owners.users.iter().collect::<Vec<_>>();
+    users.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.size));
+    for (uid, usage) in users {
+        let size = report.size(usage.size);
+        let name = names.display_user(*uid);
+        writeln!(w, "  user {name} {size}, {} file(s)", usage.num_files)?;
+    }
+
+    let mut groups = owners.groups.iter().collect::<Vec<_>>();
+    groups.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.size));
+    for (gid, usage) in groups {
+        let size = report.size(usage.size);
+        let name = names.display_group(*gid);
+        writeln!(w, "  group {name} {size}, {} file(s)", usage.num_files)?;
+    }
+    Ok(())
+}
+
+fn report_ages(w: &mut impl Write, report: &Report, meta: &DirMeta) -> io::Result<()> {
+    let Some(ages) = &meta.ages else {
+        return Ok(());
+    };
+
+    for bucket in AgeBucket::ALL {
+        let size = ages.get(bucket);
+        if size > 0 {
+            let size = report.size(size);
+            writeln!(w, "  age {} {size}", bucket.label())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! Reports the duplicate files and directory trees.
+
+use std::io::{self, Write};
+
+use anyhow::Context;
+use shr::{DupeFinder, DupeGroup, Shr, TreeDupeFinder};
+
+use crate::args::{DupesArgs, Units};
+
+/// Scans the directory and reports the duplicate groups ranked by
+/// reclaimable bytes.
+pub async fn run(args: DupesArgs, w: &mut impl Write) -> anyhow::Result<()> {
+    let shr = args.scan.apply(shr::Shr::from_roots(args.dirs));
+    let groups = if args.trees {
+        find_trees(shr).await
+    } else {
+        find_files(shr).await?
+    };
+    let units = args.units.unwrap_or_default();
+
+    for group in &groups {
+        if args.json {
+            serde_json::to_writer(&mut *w, group).context("failed to serialize group")?;
+            w.write_all(b"\n").context("failed to write newline")?;
+        } else {
+            report_group(w, group, units)?;
+        }
+    }
+
+    if !args.json {
+        let total = groups.iter().map(DupeGroup::reclaimable).sum::<u64>();
+        let total = shr::utils::human_readable_number(total, units.as_str());
+        writeln!(
+            w,
+            "{} duplicate group(s), {total} reclaimable",
+            groups.len()
+        )?;
+    }
+    Ok(())
+}
+
+async fn find_files(shr: Shr) -> anyhow::Result<Vec<DupeGroup>> {
+    let mut rx = shr.run().await;
+    let mut finder = DupeFinder::default();
+    finder.collect(&mut rx).await;
+    Ok(tokio::task::spawn_blocking(move || finder.find()).await?)
+}
+
+async fn find_trees(shr: Shr) -> Vec<DupeGroup> {
+    let mut rx = shr.with_fingerprints(true).run().await;
+    let mut finder = TreeDupeFinder::default();
+    while let Some(event) = rx.recv().await {
+        finder.add(&event.to_raw());
+    }
+    finder.find(|path| rx.get_path(path))
+}
+
+fn report_group(w: &mut impl Write, group: &DupeGroup, units: Units) -> io::Result<()> {
+    let size = shr::utils::human_readable_number(group.size, units.as_str());
+    let reclaimable = shr::utils::human_readable_number(group.reclaimable(), units.as_str());
+    let copies = group.paths.len();
+    writeln!(w, "{copies} copies of {size}, {reclaimable} reclaimable")?;
+    for path in &group.paths {
+        writeln!(w, "  {}", path.display())?;
+    }
+    Ok(())
+}
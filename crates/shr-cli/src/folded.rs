@@ -0,0 +1,59 @@
+//! Reports the scan as folded stacks, e.g. `usr;share;doc 1024`, which can be
+//! drawn as a flame graph by
+//! [inferno](https://github.com/jonhoo/inferno) or `flamegraph.pl`.
+
+use std::io::Write;
+
+use anyhow::Context;
+use shr::ShrRx;
+
+use crate::{
+    args::Report,
+    tree::{Node, Prune, Tree},
+};
+
+/// The frame of the bytes not reported as entries of their own.
+const OTHER: &str = "(other)";
+
+/// Waits for the scan to finish and writes the stacks in bytes. The entries
+/// deeper than `--max-depth` are folded into their directories, and those
+/// smaller than `--min-size` are aggregated into an "(other)" frame.
+pub async fn report(rx: &mut ShrRx, report: &Report, w: &mut impl Write) -> anyhow::Result<()> {
+    let tree = Tree::collect(rx).await;
+    let prune = Prune::new(report);
+
+    // Walks the tree without recursion, since the tree can be deep.
+    let mut frames = Vec::<String>::new();
+    let mut stack = tree.roots.iter().rev().copied().collect::<Vec<_>>();
+    while let Some(id) = stack.pop() {
+        let node = tree.node(id);
+        frames.truncate(node.depth);
+        frames.push(frame(node));
+
+        let children = node
+            .children
+            .iter()
+            .copied()
+            .filter(|child| prune.keeps(tree.node(*child)))
+            .collect::<Vec<_>>();
+        let reported = children.iter().map(|child| tree.node(*child).size).sum();
+        let rest = node.size.saturating_sub(reported);
+        if children.is_empty() {
+            if node.size > 0 {
+                writeln!(w, "{} {}", frames.join(";"), node.size)?;
+            }
+        } else if rest > 0 {
+            writeln!(w, "{};{OTHER} {rest}", frames.join(";"))?;
+        }
+        stack.extend(children.into_iter().rev());
+    }
+
+    w.flush().context("failed to write folded stacks")?;
+    Ok(())
+}
+
+/// Gets the frame of the entry. The semicolons and line breaks, which cannot
+/// be escaped in the format, are replaced.
+fn frame(node: &Node) -> String {
+    node.name().replace(';', ":").replace(['\n', '\r'], " ")
+}
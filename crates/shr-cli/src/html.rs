@@ -0,0 +1,75 @@
+//! Reports the scan as a self-contained HTML page, with a zoomable treemap,
+//! sortable tables and breadcrumbs. The page loads nothing from the network.
+
+use std::{borrow::Cow, io::Write};
+
+use anyhow::Context;
+use serde_json::json;
+use shr::{PathId, ShrRx};
+
+use crate::{
+    args::Report,
+    tree::{Prune, Tree},
+};
+
+/// The page, whose placeholder is replaced by the data.
+const TEMPLATE: &str = include_str!("report.html");
+/// The placeholder of the data in the page.
+const PLACEHOLDER: &str = "/*SHR_DATA*/null";
+/// The depth of the entries in the page, unless `--max-depth` is given.
+const DEFAULT_DEPTH: usize = 4;
+/// The minimum size of the entries in the page relative to the total, unless
+/// `--min-size` is given.
+const DEFAULT_MIN_FRACTION: f64 = 0.001;
+
+/// An entry in the page, as `[name, size, files, is_dir, parent]`. The parent
+/// is the index of the entry, or `-1` for the top.
+type Entry<'a> = (Cow<'a, str>, u64, usize, bool, isize);
+
+/// Waits for the scan to finish and writes the page. The tree is pruned by
+/// `--max-depth` and `--min-size`, or by [`DEFAULT_DEPTH`] and
+/// [`DEFAULT_MIN_FRACTION`] to keep the page small, and the pruned bytes are
+/// shown as "(other)".
+pub async fn report(rx: &mut ShrRx, report: &Report, w: &mut impl Write) -> anyhow::Result<()> {
+    let tree = Tree::collect(rx).await;
+    let prune = Prune {
+        max_depth: report.max_depth.or(Some(DEFAULT_DEPTH)),
+        min_size: report
+            .min_size
+            .unwrap_or((tree.size as f64 * DEFAULT_MIN_FRACTION) as u64),
+    };
+
+    // Flattens the tree in pre-order without recursion, since the tree can be
+    // deep. The roots are grouped under a total if there are several.
+    let mut entries = Vec::<Entry>::new();
+    let mut stack = Vec::<(PathId, isize)>::new();
+    if let &[root] = tree.roots.as_slice() {
+        stack.push((root, -1));
+    } else {
+        entries.push(("(total)".into(), tree.size, tree.num_files, true, -1));
+        stack.extend(tree.roots.iter().rev().map(|root| (*root, 0)));
+    }
+    while let Some((id, parent)) = stack.pop() {
+        let node = tree.node(id);
+        let index = entries.len() as isize;
+        entries.push((node.name(), node.size, node.num_files, node.is_dir, parent));
+        let children = node.children.iter().rev();
+        let children = children.filter(|child| prune.keeps(tree.node(**child)));
+        stack.extend(children.map(|child| (*child, index)));
+    }
+
+    let generated = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+    let data = json!({ "generated": generated.to_string(), "nodes": entries });
+    // `<` only appears in strings, where it is escaped to keep `</script>`
+    // from closing the script.
+    let data = data.to_string().replace('<', "\\u003c");
+
+    let (head, tail) = TEMPLATE
+        .split_once(PLACEHOLDER)
+        .context("the placeholder is missing in the template")?;
+    w.write_all(head.as_bytes())?;
+    w.write_all(data.as_bytes())?;
+    w.write_all(tail.as_bytes())
+        .context("failed to write html report")?;
+    Ok(())
+}
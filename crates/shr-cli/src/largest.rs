@@ -0,0 +1,89 @@
+//! Reports the largest files and leaf directories.
+
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+use anyhow::Context;
+use shr::{ImmutPath, PathId, ShrRx};
+
+use crate::{
+    args::{Format, Report},
+    csv::{self, Separator},
+};
+
+/// Waits for the scan to finish and reports the largest entries.
+pub async fn report(rx: &mut ShrRx, report: &Report, w: &mut impl Write) -> anyhow::Result<()> {
+    while rx.recv().await.is_some() {}
+
+    let Some(largest) = rx.largest() else {
+        return Ok(());
+    };
+    let files = resolve(rx, largest.files());
+    let dirs = resolve(rx, largest.dirs());
+
+    match report.format {
+        Format::Json => {
+            let largest = serde_json::json!({ "files": files, "dirs": dirs });
+            serde_json::to_writer(&mut *w, &largest).context("failed to serialize largest")?;
+            w.write_all(b"\n").context("failed to write newline")?;
+        }
+        Format::Csv => report_table(w, Separator::Comma, &files, &dirs)?,
+        Format::Tsv => report_table(w, Separator::Tab, &files, &dirs)?,
+        // The other formats are rejected with `--largest` by the arguments.
+        _ => {
+            writeln!(w, "Largest files:")?;
+            report_entries(w, report, &files)?;
+            writeln!(w, "Largest leaf directories:")?;
+            report_entries(w, report, &dirs)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct Entry {
+    path: ImmutPath,
+    size: u64,
+}
+
+fn resolve(rx: &ShrRx, entries: Vec<(PathId, u64)>) -> Vec<Entry> {
+    entries
+        .into_iter()
+        .filter_map(|(path, size)| {
+            let path = ImmutPath(rx.get_path(path)?);
+            Some(Entry { path, size })
+        })
+        .collect()
+}
+
+fn report_entries(w: &mut impl Write, report: &Report, entries: &[Entry]) -> io::Result<()> {
+    for entry in entries {
+        let size = report.size(entry.size);
+        writeln!(w, "  {} {size}", entry.path.display())?;
+    }
+    Ok(())
+}
+
+/// Reports the entries in CSV or TSV, one row per entry with the size in
+/// bytes.
+fn report_table(
+    w: &mut impl Write,
+    sep: Separator,
+    files: &[Entry],
+    dirs: &[Entry],
+) -> io::Result<()> {
+    csv::write_row(w, sep, ["kind", "path", "size"].map(Cow::Borrowed))?;
+    let files = files.iter().map(|entry| ("file", entry));
+    let dirs = dirs.iter().map(|entry| ("dir", entry));
+    for (kind, entry) in files.chain(dirs) {
+        let row = [
+            kind.into(),
+            entry.path.display(),
+            entry.size.to_string().into(),
+        ];
+        csv::write_row(w, sep, row)?;
+    }
+    Ok(())
+}
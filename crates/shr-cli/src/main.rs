@@ -2,46 +2,81 @@
 //!
 //! shr hunts and reports disk space.
 
+mod agent;
 mod args;
+mod check;
+mod csv;
+mod du;
+mod dupes;
+mod folded;
+mod html;
+mod largest;
+mod metrics;
+mod ncdu;
+mod svg;
+mod tree;
 
-use std::io::{self, Write};
+use std::io::Write;
 
 use anyhow::Context;
-use args::Format;
+use args::{Command, Format, ScanArgs};
 use clap::Parser;
-use shr::{EventDisplay, ImmutPath};
+use csv::Separator;
 
 use crate::args::Args;
 
+/// The exit code of `shr check` if any budget is exceeded.
+const CHECK_EXCEEDED: i32 = 1;
+/// The exit code of `shr check` if the rules cannot be checked, which is
+/// distinct from [`CHECK_EXCEEDED`].
+const CHECK_ERROR: i32 = 2;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let (mut rx, f) = Args::parse().build().await;
-    let mut stdout = std::io::stdout().lock();
-    match f {
-        Format::Du => loop {
-            let event = rx.recv().await.map(|event| event.display());
-            match event {
-                Some(EventDisplay::DirFinish {
-                    path,
-                    size,
-                    num_files,
-                }) => {
-                    report_entry(&mut stdout, path, size, num_files)?;
-                }
-                Some(EventDisplay::FileFinish {
-                    path,
-                    size,
-                    parent: _,
-                }) => {
-                    report_entry(&mut stdout, path, size, 0)?;
-                }
-                Some(EventDisplay::Dir { .. }) => {}
-                None => break,
+    let args = Args::parse();
+    let checking = matches!(args.command, Some(Command::Check(_)));
+    match run(args).await {
+        Err(e) if checking => {
+            eprintln!("Error: {e:?}");
+            std::process::exit(CHECK_ERROR);
+        }
+        result => result,
+    }
+}
+
+/// Runs the command with the config applied.
+async fn run(mut args: Args) -> anyhow::Result<()> {
+    let config = shr::Config::load(args.profile())?;
+    args.apply_config(&config)?;
+    if let Err(e) = args.validate() {
+        e.exit();
+    }
+    match args.command {
+        Some(Command::Dupes(args)) => dupes::run(args, &mut std::io::stdout().lock()).await,
+        Some(Command::ServeMetrics(args)) => metrics::serve(args).await,
+        Some(Command::Agent(args)) => agent::run(args).await,
+        Some(Command::Check(args)) => {
+            if !check::run(args, &mut std::io::stdout().lock()).await? {
+                std::process::exit(CHECK_EXCEEDED);
             }
-        },
+            Ok(())
+        }
+        None => scan(args.scan).await,
+    }
+}
+
+async fn scan(args: ScanArgs) -> anyhow::Result<()> {
+    let (mut rx, report) = args.build().await?;
+    let mut stdout = std::io::stdout().lock();
+    if report.largest {
+        return largest::report(&mut rx, &report, &mut stdout).await;
+    }
+    match report.format {
+        Format::Du => du::report(&mut rx, &report, &mut stdout).await?,
         Format::Json => loop {
             let event = rx.recv().await;
             match event {
+                Some(event) if !report.is_stale(event.as_raw()) => {}
                 Some(event) => {
                     serde_json::to_writer(&mut stdout, &event)
                         .context("failed to serialize event")?;
@@ -50,26 +85,34 @@ async fn main() -> anyhow::Result<()> {
                 None => break,
             }
         },
+        Format::Stream => stream(&mut rx, &mut stdout).await?,
+        Format::Log => shr::record_events(&mut rx, &mut stdout, shr::LogFormat::Json).await?,
+        Format::Ncdu => ncdu::report(&mut rx, &mut stdout).await?,
+        Format::Csv => csv::report(&mut rx, &report, Separator::Comma, &mut stdout).await?,
+        Format::Tsv => csv::report(&mut rx, &report, Separator::Tab, &mut stdout).await?,
+        Format::Html => html::report(&mut rx, &report, &mut stdout).await?,
+        Format::Openmetrics => metrics::report(&mut rx, &report, &mut stdout).await?,
+        Format::Folded => folded::report(&mut rx, &report, &mut stdout).await?,
+        Format::Svg => svg::report(&mut rx, &report, report.chart, &mut stdout).await?,
     }
 
     Ok(())
 }
 
-fn report_entry(
-    w: &mut impl Write,
-    path: Option<ImmutPath>,
-    size: u64,
-    num_files: usize,
-) -> io::Result<()> {
-    let Some(path) = path else {
-        return Ok(());
-    };
-    let size = shr::utils::human_readable_number(size, "si");
-    let path = path.as_ref().display();
-    if num_files > 0 {
-        writeln!(w, "{path} {size}, {num_files} file(s)")?;
-    } else {
-        writeln!(w, "{path} {size}")?;
+/// Reports the events in the compact stream format.
+async fn stream(rx: &mut shr::ShrRx, w: &mut impl Write) -> anyhow::Result<()> {
+    let mut encoder = shr::StreamEncoder::default();
+    let mut records = vec![shr::StreamRecord::header()];
+    loop {
+        for record in records.drain(..) {
+            serde_json::to_writer(&mut *w, &record).context("failed to serialize record")?;
+            w.write_all(b"\n").context("failed to write newline")?;
+        }
+        let Some(event) = rx.recv().await else {
+            break;
+        };
+        let event = event.to_raw();
+        encoder.encode(event, |id| rx.get_path(id), &mut records);
     }
     Ok(())
 }
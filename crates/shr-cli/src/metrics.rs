@@ -0,0 +1,182 @@
+//! Reports the scan in the [OpenMetrics](https://openmetrics.io) text format,
+//! which can be scraped by Prometheus or collected by the textfile collector
+//! of node_exporter.
+
+use std::{
+    convert::Infallible,
+    fmt::Write as _,
+    io::Write,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context;
+use http_body_util::Full;
+use hyper::{
+    Method, Request, Response, StatusCode, body::Bytes, server::conn::http1, service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use shr::ShrRx;
+
+use crate::{
+    args::{Report, ServeMetricsArgs},
+    tree::{Prune, Tree},
+};
+
+/// The depth of the directories to report, unless `--max-depth` is given.
+const DEFAULT_DEPTH: usize = 2;
+/// The content type of the text format.
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Waits for the scan to finish and writes the gauges of the directories.
+pub async fn report(rx: &mut ShrRx, report: &Report, w: &mut impl Write) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let prune = Prune {
+        max_depth: Some(report.max_depth.unwrap_or(DEFAULT_DEPTH)),
+        ..Prune::new(report)
+    };
+    let tree = Tree::collect_dirs(rx, prune).await;
+    let text = render(&tree, prune, start.elapsed());
+    w.write_all(text.as_bytes())
+        .context("failed to write metrics")?;
+    Ok(())
+}
+
+/// Rescans the directories periodically, and serves the metrics of the last
+/// scan at `/metrics`.
+pub async fn serve(args: ServeMetricsArgs) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .with_context(|| format!("failed to listen on {}", args.listen))?;
+    eprintln!("serving metrics at http://{}/metrics", args.listen);
+
+    let metrics = Arc::new(RwLock::new(None));
+    tokio::spawn(rescan(args, metrics.clone()));
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let service = service_fn(|req| {
+                let response = respond(&req, &metrics.read().unwrap());
+                async move { Ok::<_, Infallible>(response) }
+            });
+            let conn = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            if let Err(e) = conn.await {
+                eprintln!("failed to serve connection: {e}");
+            }
+        });
+    }
+}
+
+/// Scans the directories in a loop, replacing the metrics after each scan.
+async fn rescan(args: ServeMetricsArgs, metrics: Arc<RwLock<Option<String>>>) {
+    let prune = Prune {
+        max_depth: Some(args.max_depth.unwrap_or(DEFAULT_DEPTH)),
+        min_size: args.min_size.unwrap_or(0),
+    };
+    loop {
+        let start = Instant::now();
+        let shr = shr::Shr::from_roots(args.dirs.clone())
+            .with_max_depth(args.max_depth.unwrap_or(DEFAULT_DEPTH));
+        let mut rx = args.scan.apply(shr).run().await;
+        let tree = Tree::collect_dirs(&mut rx, prune).await;
+        let text = render(&tree, prune, start.elapsed());
+        *metrics.write().unwrap() = Some(text);
+        tokio::time::sleep(args.interval).await;
+    }
+}
+
+/// Responds to a request with the metrics, which are unavailable until the
+/// first scan finishes.
+fn respond<B>(req: &Request<B>, metrics: &Option<String>) -> Response<Full<Bytes>> {
+    let (status, content_type, body) = match (req.method(), req.uri().path(), metrics) {
+        (&Method::GET, "/metrics", Some(metrics)) => {
+            (StatusCode::OK, CONTENT_TYPE, metrics.clone())
+        }
+        (&Method::GET, "/metrics", None) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "text/plain",
+            "the first scan is not finished yet\n".to_owned(),
+        ),
+        _ => (
+            StatusCode::NOT_FOUND,
+            "text/plain",
+            "not found\n".to_owned(),
+        ),
+    };
+    Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+/// Renders the gauges of the directories kept by `prune`, which are at most
+/// [`DEFAULT_DEPTH`] levels deep unless `--max-depth` is given.
+fn render(tree: &Tree, prune: Prune, duration: Duration) -> String {
+    let mut dirs = tree
+        .nodes
+        .values()
+        .filter(|node| node.is_dir && prune.keeps(node))
+        .map(|node| (escape(&shr::display_path(&node.path)), node))
+        .collect::<Vec<_>>();
+    dirs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut text = String::new();
+    let name = "shr_directory_size_bytes";
+    family(
+        &mut text,
+        name,
+        "The apparent size of the directory, recursively.",
+    );
+    for (path, dir) in &dirs {
+        let _ = writeln!(text, "{name}{{path=\"{path}\"}} {}", dir.size);
+    }
+    let name = "shr_directory_files";
+    family(
+        &mut text,
+        name,
+        "The number of files in the directory, recursively.",
+    );
+    for (path, dir) in &dirs {
+        let _ = writeln!(text, "{name}{{path=\"{path}\"}} {}", dir.num_files);
+    }
+
+    let name = "shr_scan_duration_seconds";
+    family(&mut text, name, "The duration of the last scan.");
+    let _ = writeln!(text, "{name} {:.3}", duration.as_secs_f64());
+    let name = "shr_scan_timestamp_seconds";
+    family(&mut text, name, "The time when the last scan finished.");
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+    let _ = writeln!(text, "{name} {}", now.map_or(0, |now| now.as_secs()));
+
+    text.push_str("# EOF\n");
+    text
+}
+
+/// Writes the metadata of a gauge family, whose unit is the suffix of the
+/// name if any.
+fn family(text: &mut String, name: &str, help: &str) {
+    let _ = writeln!(text, "# TYPE {name} gauge");
+    for unit in ["bytes", "seconds"] {
+        if name.ends_with(unit) {
+            let _ = writeln!(text, "# UNIT {name} {unit}");
+        }
+    }
+    let _ = writeln!(text, "# HELP {name} {help}");
+}
+
+/// Escapes the label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
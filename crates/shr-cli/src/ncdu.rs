@@ -0,0 +1,136 @@
+//! Reports the scan in the JSON export format of
+//! [ncdu](https://dev.yorhel.nl/ncdu/jsonfmt), which can be loaded by
+//! `ncdu -f`.
+
+use std::{io::Write, path::Path};
+
+use anyhow::{Context, bail};
+use serde_json::json;
+use shr::{NCDU_MAJOR, ShrRx, write_ncdu_name};
+
+use crate::tree::{Node, Tree};
+
+/// The minor version of the ncdu export format.
+const NCDU_MINOR: u64 = 2;
+
+/// Waits for the scan to finish and writes the export. The sizes are the
+/// apparent sizes, since the disk usages are not collected.
+pub async fn report(rx: &mut ShrRx, w: &mut impl Write) -> anyhow::Result<()> {
+    let tree = Tree::collect(rx).await;
+    let &[root] = tree.roots.as_slice() else {
+        bail!(
+            "the ncdu format only supports a single root, but {} are scanned",
+            tree.roots.len()
+        );
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let meta = json!({
+        "progname": "shr",
+        "progver": env!("CARGO_PKG_VERSION"),
+        "timestamp": timestamp,
+    });
+    write!(w, "[{NCDU_MAJOR},{NCDU_MINOR},{meta},")?;
+
+    // Writes the tree without recursion, since the tree can be deep.
+    write_entry(w, tree.node(root))?;
+    let mut stack = vec![tree.node(root).children.iter()];
+    while let Some(children) = stack.last_mut() {
+        let Some(child) = children.next() else {
+            stack.pop();
+            w.write_all(b"]")?;
+            continue;
+        };
+        w.write_all(b",")?;
+        let child = tree.node(*child);
+        write_entry(w, child)?;
+        if child.is_dir {
+            stack.push(child.children.iter());
+        }
+    }
+
+    w.write_all(b"]\n").context("failed to write ncdu export")?;
+    Ok(())
+}
+
+/// Writes a file, or opens the array of a directory. The names are written as
+/// raw bytes like ncdu, so that the names which are not valid UTF-8 are kept.
+fn write_entry(w: &mut impl Write, node: &Node) -> anyhow::Result<()> {
+    let name = match node.path.file_name() {
+        Some(name) if node.parent.is_some() => Path::new(name),
+        _ => &node.path,
+    };
+    if node.is_dir {
+        w.write_all(b"[{\"name\":")?;
+        write_ncdu_name(w, name)?;
+        w.write_all(b"}")?;
+    } else {
+        w.write_all(b"{\"name\":")?;
+        write_ncdu_name(w, name)?;
+        write!(w, ",\"asize\":{}}}", node.size)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn import_export() {
+        let mut export = b"[1,2,{\"progname\":\"ncdu\"},".to_vec();
+        let tree = concat!(
+            "[{\"name\":\"/root\"},",
+            "[{\"name\":\"dir \\\"\\\\\\n\\u0001\"},{\"name\":\"a\",\"asize\":3}],",
+            "{\"name\":\"b\\t\\u007f\u{e9}\",\"asize\":2}"
+        )
+        .as_bytes()
+        .to_vec();
+        // A name which is not valid UTF-8, written as it is.
+        #[cfg(unix)]
+        let tree = [tree, b",{\"name\":\"c\xFF\",\"asize\":1}]".to_vec()].concat();
+        #[cfg(not(unix))]
+        let tree = [tree, b"]".to_vec()].concat();
+        export.extend_from_slice(&tree);
+        export.extend_from_slice(b"]\n");
+
+        let mut rx = shr::load_ncdu(export.as_slice(), None).unwrap();
+        let mut w = Vec::new();
+        report(&mut rx, &mut w).await.unwrap();
+
+        let meta = w.iter().position(|&b| b == b'}').unwrap();
+        assert!(w.starts_with(b"[1,2,{\"progname\":\"shr\""));
+        assert_eq!(w[meta + 2..], export[export.len() - tree.len() - 2..]);
+    }
+
+    /// Imports the export and exports it again, without the metadata.
+    async fn round_trip(export: &[u8]) -> Vec<u8> {
+        let mut rx = shr::load_ncdu(export, None).unwrap();
+        let mut w = Vec::new();
+        report(&mut rx, &mut w).await.unwrap();
+        let meta = w.iter().position(|&b| b == b'}').unwrap();
+        [b"[1,2,{}".as_slice(), &w[meta + 1..]].concat()
+    }
+
+    #[tokio::test]
+    async fn round_trip_hard_links() {
+        let export = concat!(
+            r#"[1,2,{},[{"name":"/r","dev":1},"#,
+            r#"{"name":"a","asize":10,"ino":7,"hlnkc":true},"#,
+            r#"[{"name":"d"},{"name":"b","asize":10,"ino":7,"hlnkc":true}]]]"#
+        );
+        let once = round_trip(export.as_bytes()).await;
+        // The second link is counted as empty, which is kept by the export.
+        assert_eq!(
+            String::from_utf8_lossy(&once),
+            concat!(
+                r#"[1,2,{},[{"name":"/r"},{"name":"a","asize":10},"#,
+                r#"[{"name":"d"},{"name":"b","asize":0}]]]"#,
+                "\n"
+            )
+        );
+        assert_eq!(round_trip(&once).await, once);
+    }
+}
@@ -0,0 +1,489 @@
+//! Reports the scan as a static SVG chart, either a squarified treemap or a
+//! radial sunburst.
+
+use std::{
+    f64::consts::{PI, TAU},
+    fmt::Write as _,
+    io::Write,
+};
+
+use anyhow::Context;
+use shr::{PathId, utils::human_readable_number};
+
+use crate::{
+    args::{Chart, Report, Units},
+    tree::{Node, Prune, Tree},
+};
+
+/// The size of the treemap.
+const TREEMAP_SIZE: (f64, f64) = (1200., 800.);
+/// The radius of the sunburst.
+const SUNBURST_RADIUS: f64 = 400.;
+/// The number of rings in the sunburst, unless `--max-depth` is given.
+const SUNBURST_RINGS: usize = 6;
+/// The height of the directory headers in the treemap.
+const HEADER: f64 = 16.;
+/// The approximate width of a character in the labels.
+const CHAR_WIDTH: f64 = 6.5;
+
+/// Waits for the scan to finish and writes the chart. The tree is pruned by
+/// `--max-depth` and `--min-size`.
+pub async fn report(
+    rx: &mut shr::ShrRx,
+    report: &Report,
+    chart: Chart,
+    w: &mut impl Write,
+) -> anyhow::Result<()> {
+    let tree = Tree::collect(rx).await;
+    let prune = Prune::new(report);
+    let svg = match chart {
+        Chart::Treemap => treemap(&tree, prune, report.units),
+        Chart::Sunburst => sunburst(&tree, prune, report.units),
+    };
+    w.write_all(svg.as_bytes())
+        .context("failed to write svg report")?;
+    Ok(())
+}
+
+/// A rectangle in the chart.
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+/// Draws the nested rectangles without recursion, since the tree can be
+/// deep. The rectangles too small to see are not drawn.
+fn treemap(tree: &Tree, prune: Prune, units: Units) -> String {
+    let (width, height) = TREEMAP_SIZE;
+    let mut svg = header(width, height);
+
+    let bounds = Rect {
+        x: 0.,
+        y: 0.,
+        w: width,
+        h: height,
+    };
+    let mut stack = layout(tree, &tree.roots, tree.size, bounds)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (id, rect))| (id, rect, hue(i)))
+        .collect::<Vec<_>>();
+    while let Some((id, rect, hue)) = stack.pop() {
+        let node = tree.node(id);
+        let lightness = 80 - (node.depth.min(6) * 6);
+        let _ = write!(
+            svg,
+            r##"<g><title>{}</title><rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="hsl({hue},55%,{lightness}%)" stroke="#fff" stroke-width="0.5"/>"##,
+            escape(&title(node, units)),
+            rect.x,
+            rect.y,
+            rect.w,
+            rect.h,
+        );
+        let label = label(node, units);
+        if rect.h >= 12. && rect.w >= label.chars().count() as f64 * CHAR_WIDTH + 4. {
+            let _ = write!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+                rect.x + 2.,
+                rect.y + 11.,
+                escape(&label),
+            );
+        }
+        svg.push_str("</g>\n");
+
+        if !node.is_dir || rect.w < 20. || rect.h < HEADER + 12. {
+            continue;
+        }
+        let inner = Rect {
+            x: rect.x + 2.,
+            y: rect.y + HEADER,
+            w: rect.w - 4.,
+            h: rect.h - HEADER - 2.,
+        };
+        let children = node
+            .children
+            .iter()
+            .copied()
+            .filter(|child| prune.keeps(tree.node(*child)))
+            .collect::<Vec<_>>();
+        let children = layout(tree, &children, node.size, inner);
+        stack.extend(children.into_iter().map(|(id, rect)| (id, rect, hue)));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Lays out the entries in the rectangle in proportion to `total`, leaving
+/// the rest of the bytes blank.
+fn layout(tree: &Tree, ids: &[PathId], total: u64, bounds: Rect) -> Vec<(PathId, Rect)> {
+    let mut items = ids
+        .iter()
+        .map(|id| (*id, tree.node(*id).size))
+        .filter(|(_, size)| *size > 0)
+        .collect::<Vec<_>>();
+    items.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    let rest = total.saturating_sub(items.iter().map(|(_, size)| size).sum());
+
+    let mut sizes = items.iter().map(|(_, size)| *size).collect::<Vec<_>>();
+    sizes.push(rest);
+    let rects = squarify(&sizes, bounds);
+    items
+        .into_iter()
+        .zip(rects)
+        .map(|((id, _), rect)| (id, rect))
+        .filter(|(_, rect)| rect.w >= 1. && rect.h >= 1.)
+        .collect()
+}
+
+/// Lays out the sizes in the rectangle with the squarified algorithm, which
+/// keeps the aspect ratios close to one. The sizes are sorted from the
+/// largest, except that the last one may be the blank rest.
+fn squarify(sizes: &[u64], bounds: Rect) -> Vec<Rect> {
+    let Rect {
+        mut x,
+        mut y,
+        mut w,
+        mut h,
+    } = bounds;
+    let mut total = sizes.iter().sum::<u64>() as f64;
+    let mut rects = Vec::with_capacity(sizes.len());
+    let mut rest = sizes.iter().map(|size| *size as f64).peekable();
+    while rest.peek().is_some() && total > 0. && w > 0. && h > 0. {
+        let short = w.min(h);
+        let scale = w * h / total;
+        let mut row = Vec::new();
+        let mut row_sum = 0.;
+        let mut best = f64::INFINITY;
+        while let Some(&size) = rest.peek() {
+            let area = size * scale;
+            let sum = row_sum + area;
+            let largest = row.first().map_or(area, |first| first * scale).max(area);
+            let smallest = area.min(row.last().map_or(area, |last| last * scale));
+            let worst =
+                (short * short * largest / (sum * sum)).max(sum * sum / (short * short * smallest));
+            if worst > best {
+                break;
+            }
+            best = worst;
+            row_sum = sum;
+            row.push(size);
+            rest.next();
+        }
+        if row.is_empty() {
+            // Only zero sizes are left.
+            break;
+        }
+
+        let thick = row_sum / short;
+        let mut offset = 0.;
+        for size in &row {
+            let len = size * scale / thick;
+            rects.push(if w >= h {
+                Rect {
+                    x,
+                    y: y + offset,
+                    w: thick,
+                    h: len,
+                }
+            } else {
+                Rect {
+                    x: x + offset,
+                    y,
+                    w: len,
+                    h: thick,
+                }
+            });
+            offset += len;
+        }
+        if w >= h {
+            x += thick;
+            w -= thick;
+        } else {
+            y += thick;
+            h -= thick;
+        }
+        total -= row.iter().sum::<f64>();
+    }
+    rects
+}
+
+/// Draws the rings of the sunburst without recursion. The center is the root,
+/// or the total of several roots, and each ring is one level deeper.
+fn sunburst(tree: &Tree, prune: Prune, units: Units) -> String {
+    let size = SUNBURST_RADIUS * 2.;
+    let mut svg = header(size, size);
+    let (cx, cy) = (SUNBURST_RADIUS, SUNBURST_RADIUS);
+
+    // Several roots are placed in the first ring around their total.
+    let center = match tree.roots.as_slice() {
+        &[root] => Some(root),
+        _ => None,
+    };
+    let deepest = tree
+        .nodes
+        .values()
+        .map(|node| node.depth)
+        .max()
+        .unwrap_or(0);
+    let depth = prune.max_depth.unwrap_or(SUNBURST_RINGS).min(deepest);
+    let rings = depth + 1 + usize::from(center.is_none());
+    let ring = SUNBURST_RADIUS / rings as f64;
+
+    let (name, size, tooltip) = match center {
+        Some(root) => {
+            let root = tree.node(root);
+            (root.name().into_owned(), root.size, title(root, units))
+        }
+        None => {
+            let size = tree.size;
+            let tooltip = format!("(total)\n{}", human_readable_number(size, units.as_str()));
+            ("(total)".to_owned(), size, tooltip)
+        }
+    };
+    let _ = write!(
+        svg,
+        r##"<g><title>{}</title><circle cx="{cx}" cy="{cy}" r="{ring:.1}" fill="#cbd5e0"/><text x="{cx}" y="{cy}" text-anchor="middle">{}</text><text x="{cx}" y="{:.1}" text-anchor="middle">{}</text></g>"##,
+        escape(&tooltip),
+        escape(&name),
+        cy + 14.,
+        human_readable_number(size, units.as_str()),
+    );
+    svg.push('\n');
+
+    let children = match center {
+        Some(root) => &tree.node(root).children,
+        None => &tree.roots,
+    };
+    let mut stack = Vec::new();
+    push_arcs(tree, prune, children, size, (0., TAU), 1, None, &mut stack);
+    while let Some(arc) = stack.pop() {
+        let node = tree.node(arc.id);
+        let (r0, r1) = (arc.ring as f64 * ring, (arc.ring + 1) as f64 * ring);
+        let lightness = 45 + (arc.ring.min(6) * 6);
+        let _ = write!(
+            svg,
+            r##"<g><title>{}</title><path d="{}" fill="hsl({},55%,{lightness}%)" stroke="#fff" stroke-width="0.5" fill-rule="evenodd"/>"##,
+            escape(&title(node, units)),
+            annulus(cx, cy, r0, r1, arc.angles),
+            arc.hue,
+        );
+
+        let name = node.name();
+        let (a0, a1) = arc.angles;
+        let mid = (r0 + r1) / 2.;
+        if r1 - r0 >= 12. && mid * (a1 - a0) >= name.chars().count() as f64 * CHAR_WIDTH + 4. {
+            // The label follows the ring, and is flipped on the lower half to
+            // keep it upright.
+            let angle = (a0 + a1) / 2.;
+            let (x, y) = polar(cx, cy, mid, angle);
+            let mut degrees = angle.to_degrees();
+            if angle > PI / 2. && angle < PI * 1.5 {
+                degrees += 180.;
+            }
+            let _ = write!(
+                svg,
+                r#"<text x="{x:.1}" y="{y:.1}" text-anchor="middle" dominant-baseline="middle" transform="rotate({degrees:.1} {x:.1} {y:.1})">{}</text>"#,
+                escape(&name),
+            );
+        }
+        svg.push_str("</g>\n");
+
+        if node.is_dir && arc.ring + 1 < rings {
+            let children = &node.children;
+            let hue = Some(arc.hue);
+            push_arcs(
+                tree,
+                prune,
+                children,
+                node.size,
+                arc.angles,
+                arc.ring + 1,
+                hue,
+                &mut stack,
+            );
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A segment of the sunburst to draw.
+struct Segment {
+    id: PathId,
+    angles: (f64, f64),
+    ring: usize,
+    hue: usize,
+}
+
+/// Splits the angles among the children in proportion to `total`. The arcs
+/// too thin to see are not drawn.
+#[allow(clippy::too_many_arguments)]
+fn push_arcs(
+    tree: &Tree,
+    prune: Prune,
+    children: &[PathId],
+    total: u64,
+    (start, end): (f64, f64),
+    ring: usize,
+    hue: Option<usize>,
+    stack: &mut Vec<Segment>,
+) {
+    if total == 0 {
+        return;
+    }
+    let mut angle = start;
+    let scale = (end - start) / total as f64;
+    let children = children
+        .iter()
+        .filter(|child| prune.keeps(tree.node(**child)));
+    for (i, child) in children.enumerate() {
+        let span = tree.node(*child).size as f64 * scale;
+        if span >= 0.002 {
+            stack.push(Segment {
+                id: *child,
+                angles: (angle, angle + span),
+                ring,
+                hue: hue.unwrap_or_else(|| self::hue(i)),
+            });
+        }
+        angle += span;
+    }
+}
+
+/// Gets the point at the angle, clockwise from the top.
+fn polar(cx: f64, cy: f64, r: f64, angle: f64) -> (f64, f64) {
+    (cx + r * angle.sin(), cy - r * angle.cos())
+}
+
+/// Gets the path of a ring segment between the radii and the angles.
+fn annulus(cx: f64, cy: f64, r0: f64, r1: f64, (a0, a1): (f64, f64)) -> String {
+    if a1 - a0 >= TAU - 1e-6 {
+        // A full ring cannot be drawn as a single arc.
+        let (top, bottom) = (cy - r1, cy + r1);
+        let (inner_top, inner_bottom) = (cy - r0, cy + r0);
+        return format!(
+            "M{cx:.1},{top:.1}A{r1:.1},{r1:.1} 0 1 1 {cx:.1},{bottom:.1}A{r1:.1},{r1:.1} 0 1 1 {cx:.1},{top:.1}Z\
+             M{cx:.1},{inner_top:.1}A{r0:.1},{r0:.1} 0 1 0 {cx:.1},{inner_bottom:.1}A{r0:.1},{r0:.1} 0 1 0 {cx:.1},{inner_top:.1}Z"
+        );
+    }
+    let large = if a1 - a0 > PI { 1 } else { 0 };
+    let (x0, y0) = polar(cx, cy, r1, a0);
+    let (x1, y1) = polar(cx, cy, r1, a1);
+    let (x2, y2) = polar(cx, cy, r0, a1);
+    let (x3, y3) = polar(cx, cy, r0, a0);
+    format!(
+        "M{x0:.1},{y0:.1}A{r1:.1},{r1:.1} 0 {large} 1 {x1:.1},{y1:.1}L{x2:.1},{y2:.1}A{r0:.1},{r0:.1} 0 {large} 0 {x3:.1},{y3:.1}Z"
+    )
+}
+
+/// Opens the document.
+fn header(width: f64, height: f64) -> String {
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif" font-size="11">
+"#
+    )
+}
+
+/// Gets the hue of the i-th top-level entry.
+fn hue(i: usize) -> usize {
+    (i * 47) % 360
+}
+
+/// Gets the label with the name and the size.
+fn label(node: &Node, units: Units) -> String {
+    let size = human_readable_number(node.size, units.as_str());
+    format!("{} {size}", node.name())
+}
+
+/// Gets the tooltip with the full path and the size.
+fn title(node: &Node, units: Units) -> String {
+    let path = shr::display_path(&node.path);
+    format!(
+        "{path}\n{}",
+        human_readable_number(node.size, units.as_str())
+    )
+}
+
+/// Escapes the text for XML. The control characters, which are not allowed
+/// in XML even if escaped, are replaced.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' => escaped.push('\n'),
+            c if c.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    /// Asserts that the rectangles tile the bounds with areas proportional to
+    /// the sizes.
+    fn assert_tiles(sizes: &[u64], bounds: Rect) {
+        let rects = squarify(sizes, bounds);
+        let sizes = sizes.iter().take_while(|size| **size > 0);
+        assert_eq!(rects.len(), sizes.clone().count());
+
+        let total = sizes.clone().sum::<u64>() as f64;
+        let area = bounds.w * bounds.h;
+        for (size, rect) in sizes.zip(&rects) {
+            let expected = *size as f64 / total * area;
+            assert!((rect.w * rect.h - expected).abs() < EPSILON, "{rect:?}");
+            assert!(rect.x >= bounds.x - EPSILON && rect.y >= bounds.y - EPSILON);
+            assert!(rect.x + rect.w <= bounds.x + bounds.w + EPSILON);
+            assert!(rect.y + rect.h <= bounds.y + bounds.h + EPSILON);
+        }
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                let overlap_w = (a.x + a.w).min(b.x + b.w) - a.x.max(b.x);
+                let overlap_h = (a.y + a.h).min(b.y + b.h) - a.y.max(b.y);
+                assert!(overlap_w < EPSILON || overlap_h < EPSILON, "{a:?} {b:?}");
+            }
+        }
+        // The disjoint rectangles inside the bounds with the same total area
+        // tile them.
+        let covered = rects.iter().map(|rect| rect.w * rect.h).sum::<f64>();
+        assert!((covered - area).abs() < EPSILON);
+    }
+
+    #[test]
+    fn squarify_tiles() {
+        let wide = Rect {
+            x: 10.,
+            y: 20.,
+            w: 300.,
+            h: 100.,
+        };
+        let tall = Rect {
+            x: 0.,
+            y: 0.,
+            w: 40.,
+            h: 90.,
+        };
+        for bounds in [wide, tall] {
+            assert_tiles(&[6, 6, 4, 3, 2, 2, 1], bounds);
+            assert_tiles(&[1000, 1, 1], bounds);
+            assert_tiles(&[5], bounds);
+            assert_tiles(&[3, 2, 0, 0], bounds);
+        }
+        assert!(squarify(&[0, 0], wide).is_empty());
+        assert!(squarify(&[], wide).is_empty());
+    }
+}
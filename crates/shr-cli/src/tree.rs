@@ -0,0 +1,259 @@
+//! The tree of the reported entries, collected after the scan finishes.
+
+use std::{borrow::Cow, collections::HashMap, path::Path, sync::Arc};
+
+use shr::{Event, PathId, ShrRx};
+
+use crate::args::Report;
+
+/// The tree of the reported entries.
+#[derive(Default)]
+pub struct Tree {
+    /// The nodes by path ids.
+    pub nodes: HashMap<PathId, Node>,
+    /// The roots sorted by paths.
+    pub roots: Vec<PathId>,
+    /// The size of all the roots in bytes.
+    pub size: u64,
+    /// The number of files in all the roots.
+    pub num_files: usize,
+}
+
+/// An entry in the tree.
+pub struct Node {
+    /// The path to the entry.
+    pub path: Arc<Path>,
+    /// The parent directory, or `None` for the roots.
+    pub parent: Option<PathId>,
+    /// The children sorted by size, from the largest.
+    pub children: Vec<PathId>,
+    /// Whether it is a directory.
+    pub is_dir: bool,
+    /// The size in bytes, recursively.
+    pub size: u64,
+    /// The number of files, recursively.
+    pub num_files: usize,
+    /// The depth from the root, which is zero for the roots.
+    pub depth: usize,
+}
+
+/// The limits to prune the tree in the chart reports.
+#[derive(Clone, Copy)]
+pub struct Prune {
+    /// The maximum depth of the entries to keep.
+    pub max_depth: Option<usize>,
+    /// The minimum size of the entries to keep.
+    pub min_size: u64,
+}
+
+impl Prune {
+    /// Gets the limits of the report.
+    pub fn new(report: &Report) -> Self {
+        Self {
+            max_depth: report.max_depth,
+            min_size: report.min_size.unwrap_or(0),
+        }
+    }
+
+    /// Whether to keep the entry. The roots are always kept.
+    pub fn keeps(&self, node: &Node) -> bool {
+        node.parent.is_none()
+            || (self.max_depth.is_none_or(|max| node.depth <= max) && node.size >= self.min_size)
+    }
+}
+
+impl Tree {
+    /// Collects the reported entries until the scan finishes.
+    pub async fn collect(rx: &mut ShrRx) -> Self {
+        Self::collect_with(rx, None).await
+    }
+
+    /// Collects the directories kept by `prune` until the scan finishes,
+    /// without the files, so that the memory is bounded by the number of the
+    /// directories kept rather than the number of the files.
+    pub async fn collect_dirs(rx: &mut ShrRx, prune: Prune) -> Self {
+        Self::collect_with(rx, Some(prune)).await
+    }
+
+    async fn collect_with(rx: &mut ShrRx, prune: Option<Prune>) -> Self {
+        let mut tree = Tree::default();
+        while let Some(event) = rx.recv().await {
+            match event.to_raw() {
+                Event::Dir { path, parent } => tree.insert(rx, prune, path, parent, true, 0, 0),
+                Event::FileFinish {
+                    path, parent, size, ..
+                } => {
+                    if prune.is_none() {
+                        tree.insert(rx, prune, path, parent, false, size, 1);
+                    }
+                }
+                Event::DirFinish {
+                    path,
+                    size,
+                    num_files,
+                    ..
+                } => {
+                    if let Some(node) = tree.nodes.get_mut(&path) {
+                        node.size = size;
+                        node.num_files = num_files;
+                        // The subdirectories are finished and pruned first,
+                        // since they are not larger.
+                        if prune.is_some_and(|prune| !prune.keeps(node)) {
+                            tree.remove(path);
+                        }
+                    }
+                }
+                Event::Total {
+                    size, num_files, ..
+                } => {
+                    tree.size = size;
+                    tree.num_files = num_files;
+                }
+            }
+        }
+
+        tree.roots
+            .sort_by(|a, b| tree.nodes[a].path.cmp(&tree.nodes[b].path));
+        let sizes = tree
+            .nodes
+            .iter()
+            .map(|(id, node)| (*id, (node.size, node.path.clone())))
+            .collect::<HashMap<_, _>>();
+        for node in tree.nodes.values_mut() {
+            node.children.sort_by(|a, b| {
+                let (a, b) = (&sizes[a], &sizes[b]);
+                b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1))
+            });
+        }
+        tree
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert(
+        &mut self,
+        rx: &ShrRx,
+        prune: Option<Prune>,
+        path: PathId,
+        parent: Option<PathId>,
+        is_dir: bool,
+        size: u64,
+        num_files: usize,
+    ) {
+        let Some(full_path) = rx.get_path(path) else {
+            return;
+        };
+        let depth = match parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            Some(parent) => {
+                if prune.is_some_and(|prune| prune.max_depth == Some(parent.depth)) {
+                    return;
+                }
+                parent.children.push(path);
+                parent.depth + 1
+            }
+            // The parent is pruned, or deeper than the maximum depth.
+            None if parent.is_some() && prune.is_some() => return,
+            None => {
+                self.roots.push(path);
+                0
+            }
+        };
+        let node = Node {
+            path: full_path,
+            parent: parent.filter(|parent| self.nodes.contains_key(parent)),
+            children: Vec::new(),
+            is_dir,
+            size,
+            num_files,
+            depth,
+        };
+        self.nodes.insert(path, node);
+    }
+
+    /// Removes a node whose children are removed.
+    fn remove(&mut self, id: PathId) {
+        let Some(node) = self.nodes.remove(&id) else {
+            return;
+        };
+        if let Some(parent) = node.parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            parent.children.retain(|child| *child != id);
+        }
+    }
+
+    /// Gets the node by the path id.
+    pub fn node(&self, id: PathId) -> &Node {
+        &self.nodes[&id]
+    }
+}
+
+impl Node {
+    /// Gets the name to display, which is the full path for the roots.
+    pub fn name(&self) -> Cow<'_, str> {
+        match self.path.file_name() {
+            Some(name) if self.parent.is_some() => shr::display_path(Path::new(name)),
+            _ => shr::display_path(&self.path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects the directories of an ncdu export.
+    async fn collect_dirs(tree: &str, prune: Prune) -> Vec<(String, u64, usize)> {
+        let export = format!("[1,2,{{}},{tree}]");
+        let mut rx = shr::load_ncdu(export.as_bytes(), None).unwrap();
+        let tree = Tree::collect_dirs(&mut rx, prune).await;
+        let mut dirs = tree
+            .nodes
+            .values()
+            .map(|node| {
+                (
+                    shr::display_path(&node.path).into_owned(),
+                    node.size,
+                    node.num_files,
+                )
+            })
+            .collect::<Vec<_>>();
+        dirs.sort();
+        dirs
+    }
+
+    #[tokio::test]
+    async fn prune_while_collecting() {
+        let tree = concat!(
+            r#"[{"name":"/r"},{"name":"f","asize":1},"#,
+            r#"[{"name":"a"},[{"name":"b"},[{"name":"c"},{"name":"f","asize":100}]]],"#,
+            r#"[{"name":"d"},{"name":"f","asize":5},{"name":"g","asize":5}]]"#
+        );
+
+        let prune = Prune {
+            max_depth: Some(1),
+            min_size: 0,
+        };
+        let dirs = collect_dirs(tree, prune).await;
+        assert_eq!(
+            dirs,
+            [
+                ("/r".to_owned(), 111, 4),
+                ("/r/a".to_owned(), 100, 1),
+                ("/r/d".to_owned(), 10, 2),
+            ]
+        );
+
+        let prune = Prune {
+            max_depth: None,
+            min_size: 50,
+        };
+        let dirs = collect_dirs(tree, prune).await;
+        assert_eq!(
+            dirs,
+            [
+                ("/r".to_owned(), 111, 4),
+                ("/r/a".to_owned(), 100, 1),
+                ("/r/a/b".to_owned(), 100, 1),
+                ("/r/a/b/c".to_owned(), 100, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Applies the config files to the subcommands.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// Creates a file with the content, and its parent directories.
+fn write(path: &Path, content: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+/// Creates a project whose config excludes `node_modules`, and whose `ci`
+/// profile also excludes the logs.
+fn create_project(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("shr-cli-{name}-{}", std::process::id()));
+    write(&root.join("user.toml"), "");
+    write(
+        &root.join(".shr.toml"),
+        "exclude = [\"node_modules\"]\nunits = \"bytes\"\n[profile.ci]\nexclude = [\"*.log\"]\n",
+    );
+    write(&root.join("a").join("src").join("main.rs"), "fn main() {}");
+    write(
+        &root.join("a").join("node_modules").join("main.rs"),
+        "fn main() {}",
+    );
+    write(&root.join("a").join("build.log"), &"log\n".repeat(1000));
+    root
+}
+
+/// Runs `shr` in the directory, isolated from the user config.
+fn shr(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_shr"))
+        .args(args)
+        .current_dir(dir)
+        .env("SHR_CONFIG", dir.join("user.toml"))
+        .env_remove("SHR_PROFILE")
+        .env_remove("SHR_EXCLUDE")
+        .env_remove("SHR_UNITS")
+        .env_remove("SHR_FORMAT")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn check_applies_config() {
+    let root = create_project("check");
+    let default = shr(&root, &["check", "a <= 1K"]);
+    let profiled = shr(&root, &["check", "--profile", "ci", "a <= 1K"]);
+    let unknown = shr(&root, &["check", "--profile", "nope", "a <= 1K"]);
+    let missing = shr(&root, &["check", "b <= 1K"]);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    // The logs exceed the budget unless the profile excludes them.
+    assert_eq!(default.status.code(), Some(1));
+    let stdout = String::from_utf8(default.stdout).unwrap();
+    assert!(
+        stdout.contains("FAIL a <= 1K (limit 1024B): a (4012B)"),
+        "{stdout}"
+    );
+    assert_eq!(profiled.status.code(), Some(0));
+    let stdout = String::from_utf8(profiled.stdout).unwrap();
+    assert!(
+        stdout.contains("ok   a <= 1K (limit 1024B): 12B"),
+        "{stdout}"
+    );
+    assert_eq!(unknown.status.code(), Some(2));
+    // A typo in a target fails instead of disabling the budget.
+    assert_eq!(missing.status.code(), Some(2));
+    let stderr = String::from_utf8(missing.stderr).unwrap();
+    assert!(
+        stderr.contains("the target of `b <= 1K` does not exist"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn dupes_applies_config() {
+    let root = create_project("dupes");
+    let output = shr(&root, &["dupes", "a"]);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    // The copy in `node_modules` is excluded by the config.
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.ends_with("0 duplicate group(s), 0B reclaimable\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn largest_rejects_format_of_config() {
+    let root = create_project("largest");
+    let csv = shr(&root, &["--largest", "1", "--format", "csv", "a"]);
+    write(&root.join("user.toml"), "format = \"html\"\n");
+    let html = shr(&root, &["--largest", "1", "a"]);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(csv.status.code(), Some(0));
+    let stdout = String::from_utf8(csv.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "kind,path,size\nfile,a/build.log,4000\ndir,a/src,12\n"
+    );
+    // The html format set by the config cannot report the largest entries.
+    assert_eq!(html.status.code(), Some(2));
+    let stderr = String::from_utf8(html.stderr).unwrap();
+    assert!(stderr.contains("in the html format"), "{stderr}");
+}
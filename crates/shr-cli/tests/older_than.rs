@@ -0,0 +1,53 @@
+//! Filters the stale entries in the reports.
+
+use std::{
+    path::Path,
+    process::{Command, Output},
+    time::{Duration, SystemTime},
+};
+
+/// Runs `shr` in the directory, isolated from the user config.
+fn shr(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_shr"))
+        .args(args)
+        .current_dir(dir)
+        .env("SHR_CONFIG", dir.join("user.toml"))
+        .env_remove("SHR_PROFILE")
+        .env_remove("SHR_FORMAT")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn filter_csv() {
+    let root = std::env::temp_dir().join(format!("shr-cli-older-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("a").join("old")).unwrap();
+    std::fs::write(root.join("user.toml"), "").unwrap();
+    std::fs::write(root.join("a").join("new"), "new").unwrap();
+    let old = root.join("a").join("old").join("file");
+    std::fs::write(&old, "old").unwrap();
+    let year_ago = SystemTime::now() - Duration::from_secs(365 * 24 * 60 * 60);
+    std::fs::File::options()
+        .write(true)
+        .open(&old)
+        .unwrap()
+        .set_modified(year_ago)
+        .unwrap();
+
+    let csv = shr(&root, &["--format", "csv", "--older-than", "180d", "a"]);
+    let html = shr(&root, &["--format", "html", "--older-than", "180d", "a"]);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(csv.status.code(), Some(0));
+    let stdout = String::from_utf8(csv.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "path,depth,kind,size,files,parent\n\
+         a/old/file,2,file,3,1,a/old\n\
+         a/old,1,dir,3,1,a\n"
+    );
+    // The html format reports the whole tree, so it cannot filter.
+    assert_eq!(html.status.code(), Some(2));
+    let stderr = String::from_utf8(html.stderr).unwrap();
+    assert!(stderr.contains("--older-than"), "{stderr}");
+}
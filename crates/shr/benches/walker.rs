@@ -0,0 +1,67 @@
+//! Compares the walkers by scanning a generated tree, or the directory given
+//! by `SHR_BENCH_DIR`.
+//!
+//! ```bash
+//! cargo bench -p shr --features tokio --bench walker
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use shr::{Shr, Walker};
+
+/// The number of rounds to run each walker.
+const ROUNDS: usize = 7;
+
+/// Creates a tree under a long path prefix, with `width` directories of
+/// `width` subdirectories, each containing `width` files.
+fn create_tree(root: &Path, width: usize) -> PathBuf {
+    let base = root.join("a/long/path/prefix/to/resolve/again/and/again");
+    for i in 0..width {
+        for j in 0..width {
+            let dir = base.join(format!("dir-{i}/sub-{j}"));
+            std::fs::create_dir_all(&dir).unwrap();
+            for k in 0..width {
+                std::fs::write(dir.join(format!("file-{k}")), b"shr").unwrap();
+            }
+        }
+    }
+    base
+}
+
+/// Scans the directory and returns the elapsed time and the number of events.
+async fn scan(path: &Path, walker: Walker) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut rx = Shr::new(path.to_owned()).with_walker(walker).run().await;
+    let mut events = 0;
+    while rx.recv().await.is_some() {
+        events += 1;
+    }
+    (start.elapsed(), events)
+}
+
+#[tokio::main]
+async fn main() {
+    let fixture = std::env::temp_dir().join(format!("shr-bench-walker-{}", std::process::id()));
+    let path = match std::env::var_os("SHR_BENCH_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => create_tree(&fixture, 24),
+    };
+
+    for walker in [Walker::Path, Walker::Fd] {
+        // Warms up the caches of the file system.
+        let (_, events) = scan(&path, walker).await;
+        let mut times = Vec::with_capacity(ROUNDS);
+        for _ in 0..ROUNDS {
+            times.push(scan(&path, walker).await.0);
+        }
+        times.sort();
+        let median = times[ROUNDS / 2];
+        println!(
+            "{walker:?}: median {median:?}, min {:?} ({events} events)",
+            times[0]
+        );
+    }
+
+    let _ = std::fs::remove_dir_all(fixture);
+}
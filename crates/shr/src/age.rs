@@ -0,0 +1,238 @@
+//! Age-based analysis of the files by their timestamps.
+
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The timestamp used to determine the age of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum Timestamp {
+    /// The last modification time (mtime).
+    #[default]
+    Modified,
+    /// The last access time (atime).
+    Accessed,
+}
+
+impl Timestamp {
+    /// Gets the timestamp of the file in seconds since the unix epoch.
+    pub fn of(self, meta: &crate::FileMeta) -> Option<u64> {
+        match self {
+            Timestamp::Modified => meta.modified,
+            Timestamp::Accessed => meta.accessed,
+        }
+    }
+}
+
+/// The age buckets that the bytes are aggregated into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum AgeBucket {
+    /// Younger than one week.
+    Week,
+    /// Younger than one month.
+    Month,
+    /// Younger than six months.
+    HalfYear,
+    /// Older than six months.
+    Older,
+}
+
+impl AgeBucket {
+    /// All the buckets, from the youngest to the oldest.
+    pub const ALL: [AgeBucket; 4] = [
+        AgeBucket::Week,
+        AgeBucket::Month,
+        AgeBucket::HalfYear,
+        AgeBucket::Older,
+    ];
+
+    /// Gets the bucket of the age in seconds.
+    pub fn from_age(age: u64) -> Self {
+        const DAY: u64 = 24 * 60 * 60;
+        if age < 7 * DAY {
+            AgeBucket::Week
+        } else if age < 30 * DAY {
+            AgeBucket::Month
+        } else if age < 182 * DAY {
+            AgeBucket::HalfYear
+        } else {
+            AgeBucket::Older
+        }
+    }
+
+    /// Gets the label of the bucket.
+    pub fn label(self) -> &'static str {
+        match self {
+            AgeBucket::Week => "<1 week",
+            AgeBucket::Month => "<1 month",
+            AgeBucket::HalfYear => "<6 months",
+            AgeBucket::Older => "older",
+        }
+    }
+}
+
+/// The bytes of a directory, grouped by age buckets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AgeUsage {
+    /// The bytes younger than one week.
+    pub week: u64,
+    /// The bytes younger than one month.
+    pub month: u64,
+    /// The bytes younger than six months.
+    pub half_year: u64,
+    /// The bytes older than six months.
+    pub older: u64,
+    /// The newest timestamp in seconds since the unix epoch.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub newest: Option<u64>,
+}
+
+impl AgeUsage {
+    /// Gets the bytes in the bucket.
+    pub fn get(&self, bucket: AgeBucket) -> u64 {
+        match bucket {
+            AgeBucket::Week => self.week,
+            AgeBucket::Month => self.month,
+            AgeBucket::HalfYear => self.half_year,
+            AgeBucket::Older => self.older,
+        }
+    }
+
+    /// Accounts a file in the bucket, whose timestamp is `time`.
+    pub fn add_file(&mut self, bucket: AgeBucket, time: Option<u64>, size: u64) {
+        *self.get_mut(bucket) += size;
+        self.newest = self.newest.max(time);
+    }
+
+    /// Merges the usage of another directory into this one.
+    pub fn merge(&mut self, other: &AgeUsage) {
+        for bucket in AgeBucket::ALL {
+            *self.get_mut(bucket) += other.get(bucket);
+        }
+        self.newest = self.newest.max(other.newest);
+    }
+
+    fn get_mut(&mut self, bucket: AgeBucket) -> &mut u64 {
+        match bucket {
+            AgeBucket::Week => &mut self.week,
+            AgeBucket::Month => &mut self.month,
+            AgeBucket::HalfYear => &mut self.half_year,
+            AgeBucket::Older => &mut self.older,
+        }
+    }
+}
+
+/// The clock to determine the ages of files at the beginning of a scan.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AgeClock {
+    /// The timestamp to use.
+    pub by: Timestamp,
+    /// The time when the scan begins, in seconds since the unix epoch.
+    pub now: u64,
+}
+
+impl AgeClock {
+    /// Creates a clock starting from now.
+    pub fn new(by: Timestamp) -> Self {
+        Self {
+            by,
+            now: unix_secs(SystemTime::now()).unwrap_or(0),
+        }
+    }
+
+    /// Gets the bucket of a file.
+    pub fn bucket(&self, meta: &crate::FileMeta) -> Option<AgeBucket> {
+        let time = self.by.of(meta)?;
+        Some(AgeBucket::from_age(self.now.saturating_sub(time)))
+    }
+}
+
+/// Converts the time to seconds since the unix epoch.
+pub(crate) fn unix_secs(time: SystemTime) -> Option<u64> {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .as_ref()
+        .map(Duration::as_secs)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileMeta;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn bucket_boundaries() {
+        let cases = [
+            (0, AgeBucket::Week),
+            (7 * DAY - 1, AgeBucket::Week),
+            (7 * DAY, AgeBucket::Month),
+            (30 * DAY - 1, AgeBucket::Month),
+            (30 * DAY, AgeBucket::HalfYear),
+            (182 * DAY - 1, AgeBucket::HalfYear),
+            (182 * DAY, AgeBucket::Older),
+            (u64::MAX, AgeBucket::Older),
+        ];
+        for (age, bucket) in cases {
+            assert_eq!(AgeBucket::from_age(age), bucket, "{age}");
+        }
+    }
+
+    #[test]
+    fn bucket_by_clock() {
+        let now = 1_000 * DAY;
+        let clock = AgeClock {
+            by: Timestamp::Modified,
+            now,
+        };
+        let meta = |modified, accessed| FileMeta {
+            modified,
+            accessed,
+            ..FileMeta::default()
+        };
+
+        assert_eq!(
+            clock.bucket(&meta(Some(now - 7 * DAY), None)),
+            Some(AgeBucket::Month)
+        );
+        // The mtimes in the future are as young as now.
+        assert_eq!(
+            clock.bucket(&meta(Some(now + DAY), None)),
+            Some(AgeBucket::Week)
+        );
+        // The missing timestamps have no bucket.
+        assert_eq!(clock.bucket(&meta(None, Some(now))), None);
+
+        let clock = AgeClock {
+            by: Timestamp::Accessed,
+            now,
+        };
+        assert_eq!(
+            clock.bucket(&meta(Some(now), Some(now - 200 * DAY))),
+            Some(AgeBucket::Older)
+        );
+    }
+
+    #[test]
+    fn merge_usage() {
+        let mut a = AgeUsage::default();
+        a.add_file(AgeBucket::Week, Some(5), 10);
+        a.add_file(AgeBucket::Older, None, 1);
+        let mut b = AgeUsage::default();
+        b.add_file(AgeBucket::Week, Some(9), 20);
+        a.merge(&b);
+        assert_eq!(a.get(AgeBucket::Week), 30);
+        assert_eq!(a.get(AgeBucket::Older), 1);
+        assert_eq!(a.newest, Some(9));
+    }
+}
@@ -0,0 +1,168 @@
+//! Streams a scan over TCP, so that a remote machine can be browsed as if it
+//! were scanned locally.
+//!
+//! Each frame is a big-endian `u32` length followed by an [`AgentFrame`] in
+//! JSON. The frames are the records of the compact event stream, see
+//! [`crate::StreamRecord`], interleaved with [`AgentFrame::Progress`] every
+//! [`PROGRESS_INTERVAL`], which lets the client tell a slow scan from a dead
+//! connection.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use crate::{Event, ShrRx, ShrTx, StreamDecoder, StreamEncoder, StreamRecord};
+
+/// The interval between the progress frames.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// The client gives up if no frame is received in this duration.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// The maximum length of a frame, which guards against a corrupted length.
+const MAX_FRAME: u32 = 64 << 20;
+
+/// A frame of the agent protocol.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AgentFrame {
+    /// The progress of the scan so far.
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        /// The number of files and directories found.
+        entries: u64,
+        /// The number of directories not finished yet.
+        pending_dirs: u64,
+        /// The time since the scan began, in milliseconds.
+        elapsed_ms: u64,
+    },
+    /// A record of the compact event stream.
+    #[serde(untagged)]
+    Record(StreamRecord),
+}
+
+/// Writes a frame.
+pub async fn write_frame(
+    w: &mut (impl AsyncWrite + Unpin),
+    frame: &AgentFrame,
+) -> anyhow::Result<()> {
+    let payload = serde_json::to_vec(frame).context("failed to serialize frame")?;
+    let len = u32::try_from(payload.len()).context("the frame is too large")?;
+    w.write_all(&len.to_be_bytes()).await?;
+    w.write_all(&payload).await?;
+    Ok(())
+}
+
+/// Reads a frame, or `None` at the end of the stream.
+pub async fn read_frame(r: &mut (impl AsyncRead + Unpin)) -> anyhow::Result<Option<AgentFrame>> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME {
+        bail!("the frame is too large: {len} bytes");
+    }
+    let mut payload = vec![0; len as usize];
+    r.read_exact(&mut payload).await?;
+    let frame = serde_json::from_slice(&payload).context("failed to parse frame")?;
+    Ok(Some(frame))
+}
+
+/// Streams the events of a scan to the client, until the scan finishes.
+pub async fn serve_agent(rx: &mut ShrRx, stream: impl AsyncWrite + Unpin) -> anyhow::Result<()> {
+    let mut w = BufWriter::new(stream);
+    let paths = rx.resolver();
+    let mut encoder = StreamEncoder::default();
+    let mut records = vec![StreamRecord::header()];
+    let begin = Instant::now();
+    let (mut entries, mut pending_dirs) = (0u64, 0u64);
+    let mut progress = tokio::time::interval(PROGRESS_INTERVAL);
+    let frame = |entries, pending_dirs| AgentFrame::Progress {
+        entries,
+        pending_dirs,
+        elapsed_ms: begin.elapsed().as_millis() as u64,
+    };
+
+    loop {
+        for record in records.drain(..) {
+            write_frame(&mut w, &AgentFrame::Record(record)).await?;
+        }
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                let event = event.to_raw();
+                match &event {
+                    Event::Dir { .. } => {
+                        entries += 1;
+                        pending_dirs += 1;
+                    }
+                    Event::FileFinish { .. } => entries += 1,
+                    Event::DirFinish { .. } => pending_dirs = pending_dirs.saturating_sub(1),
+                    Event::Total { .. } => {}
+                }
+                encoder.encode(event, |id| paths.get_path(id), &mut records);
+            }
+            _ = progress.tick() => {
+                write_frame(&mut w, &frame(entries, pending_dirs)).await?;
+                w.flush().await?;
+            }
+        }
+    }
+
+    write_frame(&mut w, &frame(entries, pending_dirs)).await?;
+    w.flush().await?;
+    Ok(())
+}
+
+/// Connects to an agent, and receives the events as if the scan ran locally.
+/// The `largest` files and leaf directories are tracked as
+/// [`crate::Shr::with_largest`] does.
+pub async fn connect_agent(
+    addr: impl ToSocketAddrs,
+    largest: Option<usize>,
+) -> anyhow::Result<ShrRx> {
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .context("failed to connect to the agent")?;
+    let (tx, rx) = ShrTx::channel(largest);
+    let mut decoder = StreamDecoder::new(tx);
+
+    // The header is checked before returning.
+    match read_frame(&mut stream).await? {
+        Some(AgentFrame::Record(header @ StreamRecord::Header { .. })) => {
+            decoder.decode(header)?;
+        }
+        _ => bail!("the agent did not send a header"),
+    }
+
+    tokio::spawn(async move {
+        let result = async {
+            loop {
+                let frame = tokio::time::timeout(TIMEOUT, read_frame(&mut stream))
+                    .await
+                    .context("the agent stopped responding")??;
+                match frame {
+                    Some(AgentFrame::Record(record)) => {
+                        if !decoder.decode(record)? {
+                            return Ok(());
+                        }
+                    }
+                    Some(AgentFrame::Progress { .. }) => {}
+                    None => return Ok(()),
+                }
+            }
+        };
+        let result: anyhow::Result<()> = result.await;
+        if let Err(e) = result {
+            eprintln!("failed to receive from the agent: {e:#}");
+        }
+    });
+
+    Ok(rx)
+}
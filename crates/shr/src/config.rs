@@ -0,0 +1,147 @@
+//! Loads the default options from the TOML configuration files.
+//!
+//! Two files are read if they exist:
+//!
+//! - The user-level config at `$SHR_CONFIG`, or `shr/config.toml` in
+//!   `$XDG_CONFIG_HOME`, `~/.config`, or `%APPDATA%` on Windows.
+//! - The per-directory `.shr.toml`, which is the nearest one in the current
+//!   directory or its ancestors.
+//!
+//! ```toml
+//! exclude = ["node_modules", ".git"]
+//! units = "binary"
+//! threads = 4
+//! format = "du"
+//!
+//! [profile.ci]
+//! format = "json"
+//! threads = 2
+//! ```
+//!
+//! The options are resolved from the highest precedence:
+//!
+//! 1. The command line flags.
+//! 2. The environment variables, e.g. `SHR_THREADS`.
+//! 3. The selected profile of the per-directory config, then of the
+//!    user-level one.
+//! 4. The top level of the per-directory config, then of the user-level one.
+//! 5. The built-in defaults.
+//!
+//! The excludes are not overridden but combined from all the sources.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use serde::Deserialize;
+
+/// The name of the per-directory config file.
+pub const DIR_CONFIG: &str = ".shr.toml";
+
+/// The default options, either at the top level of a config file or in a
+/// profile.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The globs of the entries to skip, see [`crate::Shr::with_excludes`].
+    pub exclude: Vec<String>,
+    /// The units of the sizes, e.g. `si`, `binary` or `bytes`.
+    pub units: Option<String>,
+    /// The number of threads to scan.
+    pub threads: Option<usize>,
+    /// The output format of `shr`, e.g. `du` or `json`.
+    pub format: Option<String>,
+}
+
+impl Config {
+    /// Loads the config files, and applies the `profile` if it is given. A
+    /// profile defined in neither file is an error.
+    pub fn load(profile: Option<&str>) -> anyhow::Result<Self> {
+        let cwd = std::env::current_dir().context("failed to get current directory")?;
+        let files = [user_config(), find_dir_config(&cwd)]
+            .into_iter()
+            .flatten()
+            .map(|path| ConfigFile::read(&path))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut config = Self::default();
+        for file in &files {
+            config = config.merge(file.config.clone());
+        }
+        if let Some(name) = profile {
+            let mut found = false;
+            for file in &files {
+                if let Some(profile) = file.profiles.get(name) {
+                    config = config.merge(profile.clone());
+                    found = true;
+                }
+            }
+            if !found {
+                bail!("unknown profile: {name}");
+            }
+        }
+        Ok(config)
+    }
+
+    /// Overrides the options by the ones set in `other`, and combines the
+    /// excludes.
+    fn merge(mut self, other: Self) -> Self {
+        self.exclude.extend(other.exclude);
+        Self {
+            exclude: self.exclude,
+            units: other.units.or(self.units),
+            threads: other.threads.or(self.threads),
+            format: other.format.or(self.format),
+        }
+    }
+}
+
+/// A config file, whose `[profile.NAME]` tables are the named profiles.
+struct ConfigFile {
+    /// The options at the top level.
+    config: Config,
+    /// The profiles by names.
+    profiles: BTreeMap<String, Config>,
+}
+
+impl ConfigFile {
+    /// Reads and parses a config file.
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Parses the content of a config file.
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let profiles = match table.remove("profile") {
+            Some(profiles) => profiles.try_into()?,
+            None => BTreeMap::new(),
+        };
+        Ok(Self {
+            config: table.try_into()?,
+            profiles,
+        })
+    }
+}
+
+/// Gets the path to the user-level config, if it exists.
+fn user_config() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SHR_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA")?),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("shr").join("config.toml")).filter(|path| path.is_file())
+}
+
+/// Finds the nearest per-directory config in the directory or its ancestors.
+fn find_dir_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(DIR_CONFIG))
+        .find(|path| path.is_file())
+}
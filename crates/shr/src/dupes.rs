@@ -0,0 +1,412 @@
+//! Finds duplicate files.
+//!
+//! The candidates are grouped by the sizes reported by the scan, and then
+//! confirmed by partial and full content hashes in parallel.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::Path,
+    sync::Arc,
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+#[cfg(feature = "tokio")]
+use crate::ShrRx;
+use crate::hash::PARTIAL_SIZE;
+use crate::{ContentHash, Event, ImmutPath, PathId};
+
+/// A group of files with the same content.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DupeGroup {
+    /// The size of each file in bytes.
+    pub size: u64,
+    /// The content hash of the files.
+    pub hash: ContentHash,
+    /// The paths to the files.
+    pub paths: Vec<ImmutPath>,
+}
+
+impl DupeGroup {
+    fn new(size: u64, hash: ContentHash, mut paths: Vec<Arc<Path>>) -> Self {
+        paths.sort();
+        Self {
+            size,
+            hash,
+            paths: paths.into_iter().map(ImmutPath).collect(),
+        }
+    }
+
+    /// The bytes that could be reclaimed by keeping only one copy.
+    pub fn reclaimable(&self) -> u64 {
+        self.size * (self.paths.len() as u64).saturating_sub(1)
+    }
+}
+
+/// Collects the files of a scan and finds the duplicates in them.
+#[derive(Debug, Default)]
+pub struct DupeFinder {
+    /// The candidate files grouped by size.
+    by_size: HashMap<u64, Vec<Arc<Path>>>,
+}
+
+impl DupeFinder {
+    /// Adds a file reported by the scan. Empty files are never duplicates.
+    pub fn add(&mut self, path: Arc<Path>, size: u64) {
+        if size > 0 {
+            self.by_size.entry(size).or_default().push(path);
+        }
+    }
+
+    /// Collects all the files reported by the scan.
+    #[cfg(feature = "tokio")]
+    pub async fn collect(&mut self, rx: &mut ShrRx) {
+        while let Some(event) = rx.recv().await {
+            if let Event::FileFinish { path, size, .. } = event.data {
+                if let Some(path) = rx.get_path(path) {
+                    self.add(path, size);
+                }
+            }
+        }
+    }
+
+    /// Confirms the candidates by content hashes, and returns the duplicate
+    /// groups ranked by reclaimable bytes. This is blocking and runs in
+    /// parallel.
+    pub fn find(self) -> Vec<DupeGroup> {
+        let candidates = self
+            .by_size
+            .into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .flat_map(|(size, paths)| dedup_links(paths).into_iter().map(move |p| (size, p)))
+            .collect::<Vec<_>>();
+
+        let partial = group_by_hash(candidates, |size, path| {
+            if size <= PARTIAL_SIZE {
+                ContentHash::of_file(path)
+            } else {
+                ContentHash::of_file_head(path)
+            }
+        });
+
+        let mut groups = Vec::new();
+        let mut full_candidates = Vec::new();
+        for ((size, hash), paths) in partial {
+            if paths.len() < 2 {
+                continue;
+            }
+            if size <= PARTIAL_SIZE {
+                // The partial hash already covers the whole file.
+                groups.push(DupeGroup::new(size, hash, paths));
+            } else {
+                full_candidates.extend(paths.into_iter().map(|path| (size, path)));
+            }
+        }
+
+        let full = group_by_hash(full_candidates, |_, path| ContentHash::of_file(path));
+        groups.extend(
+            full.into_iter()
+                .filter(|(_, paths)| paths.len() > 1)
+                .map(|((size, hash), paths)| DupeGroup::new(size, hash, paths)),
+        );
+
+        groups.sort_by_key(|group| std::cmp::Reverse(group.reclaimable()));
+        groups
+    }
+}
+
+/// Collects the fingerprints of directories reported by a scan with
+/// [`crate::Shr::with_fingerprints`], and finds the identical subtrees.
+#[derive(Debug, Default)]
+pub struct TreeDupeFinder {
+    /// The parents of the directories.
+    parents: HashMap<PathId, PathId>,
+    /// The sizes and fingerprints of the directories.
+    dirs: HashMap<PathId, (u64, ContentHash)>,
+}
+
+impl TreeDupeFinder {
+    /// Adds an event reported by the scan.
+    pub fn add(&mut self, event: &Event) {
+        match event {
+            Event::Dir {
+                path,
+                parent: Some(parent),
+            } => {
+                self.parents.insert(*path, *parent);
+            }
+            Event::DirFinish {
+                path,
+                size,
+                meta: Some(meta),
+                ..
+            } if *size > 0 => {
+                if let Some(fingerprint) = meta.fingerprint {
+                    self.dirs.insert(*path, (*size, fingerprint));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Finds the identical subtrees ranked by reclaimable bytes. The subtrees
+    /// are omitted if their parents are distinct copies in another group,
+    /// which already reports them.
+    pub fn find(self, get_path: impl Fn(PathId) -> Option<Arc<Path>>) -> Vec<DupeGroup> {
+        let mut groups = HashMap::<(u64, ContentHash), Vec<PathId>>::new();
+        for (path, key) in &self.dirs {
+            groups.entry(*key).or_default().push(*path);
+        }
+
+        let is_covered = |paths: &Vec<PathId>| {
+            let parents = paths
+                .iter()
+                .map(|path| self.parents.get(path).copied())
+                .collect::<Option<HashSet<_>>>();
+            let Some(parents) = parents.filter(|parents| parents.len() == paths.len()) else {
+                return false;
+            };
+            let mut keys = parents.iter().map(|parent| self.dirs.get(parent));
+            let first = keys.next().flatten();
+            first.is_some() && keys.all(|key| key == first)
+        };
+        let mut result = groups
+            .iter()
+            .filter(|(_, paths)| paths.len() > 1 && !is_covered(paths))
+            .map(|((size, hash), paths)| {
+                let paths = paths.iter().filter_map(|path| get_path(*path)).collect();
+                DupeGroup::new(*size, *hash, paths)
+            })
+            .collect::<Vec<_>>();
+
+        result.sort_by_key(|group| std::cmp::Reverse(group.reclaimable()));
+        result
+    }
+}
+
+type HashGroups = HashMap<(u64, ContentHash), Vec<Arc<Path>>>;
+
+/// Hashes the files in parallel and groups them by sizes and hashes.
+fn group_by_hash(
+    files: Vec<(u64, Arc<Path>)>,
+    hash: impl Fn(u64, &Path) -> io::Result<ContentHash> + Sync,
+) -> HashGroups {
+    files
+        .into_par_iter()
+        .filter_map(|(size, path)| {
+            let hash = hash(size, &path)
+                .inspect_err(|e| eprintln!("failed to hash {}: {e}", path.display()))
+                .ok()?;
+            Some(((size, hash), path))
+        })
+        .fold(HashGroups::default, |mut groups, (key, path)| {
+            groups.entry(key).or_default().push(path);
+            groups
+        })
+        .reduce(HashGroups::default, |mut a, b| {
+            for (key, paths) in b {
+                a.entry(key).or_default().extend(paths);
+            }
+            a
+        })
+}
+
+/// Removes the hard links to the same file, which take no extra space.
+fn dedup_links(paths: Vec<Arc<Path>>) -> Vec<Arc<Path>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let mut seen = HashSet::new();
+        paths
+            .into_iter()
+            .filter(|path| {
+                let Ok(mt) = std::fs::metadata(path) else {
+                    return false;
+                };
+                seen.insert((mt.dev(), mt.ino()))
+            })
+            .collect()
+    }
+    #[cfg(not(unix))]
+    {
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, path::PathBuf};
+
+    use super::*;
+    use crate::DirMeta;
+
+    /// Creates a temporary directory for the test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shr-dupes-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Finds the duplicates among the files, whose paths are relative to
+    /// `dir` in the result.
+    fn find(dir: &Path, files: &[(&str, Vec<u8>)]) -> Vec<(u64, Vec<String>)> {
+        let mut finder = DupeFinder::default();
+        for (name, content) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            finder.add(path.into(), content.len() as u64);
+        }
+        let groups = finder.find();
+        groups
+            .into_iter()
+            .map(|group| {
+                let paths = group.paths.iter().map(|path| {
+                    let path = path.0.strip_prefix(dir).unwrap();
+                    path.to_string_lossy().into_owned()
+                });
+                (group.size, paths.collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_dupes_by_size_and_hashes() {
+        let dir = temp_dir("files");
+        let large = vec![7u8; PARTIAL_SIZE as usize * 2];
+        let mut tail = large.clone();
+        *tail.last_mut().unwrap() = 8;
+        let groups = find(
+            &dir,
+            &[
+                // The same sizes with different heads, refuted by the partial
+                // hashes.
+                ("a1", b"aaaa".to_vec()),
+                ("a2", b"aaaa".to_vec()),
+                ("a3", b"bbbb".to_vec()),
+                // The same heads with different tails, refuted by the full
+                // hashes.
+                ("l1", large.clone()),
+                ("l2", large.clone()),
+                ("l3", tail),
+                // A size of its own, and the empty files.
+                ("u", b"unique".to_vec()),
+                ("e1", Vec::new()),
+                ("e2", Vec::new()),
+            ],
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Ranked by the reclaimable bytes.
+        let expected = [
+            (PARTIAL_SIZE * 2, vec!["l1".to_owned(), "l2".to_owned()]),
+            (4, vec!["a1".to_owned(), "a2".to_owned()]),
+        ];
+        assert_eq!(groups, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dedup_hard_links() {
+        let dir = temp_dir("links");
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        std::fs::write(&a, b"content").unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+        std::fs::write(&c, b"content").unwrap();
+        let missing = dir.join("missing");
+
+        let paths = [&a, &b, &c, &missing].map(|path| Arc::<Path>::from(path.as_path()));
+        let deduped = dedup_links(paths.to_vec());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The link and the missing file are dropped.
+        assert_eq!(deduped, [paths[0].clone(), paths[2].clone()]);
+    }
+
+    fn id(n: usize) -> PathId {
+        PathId::from_raw(NonZeroUsize::new(n).unwrap())
+    }
+
+    /// Adds a directory with the fingerprint to the finder.
+    fn add_dir(finder: &mut TreeDupeFinder, path: usize, parent: usize, fingerprint: u8) {
+        finder.add(&Event::Dir {
+            path: id(path),
+            parent: Some(id(parent)),
+        });
+        let meta = DirMeta {
+            fingerprint: Some(ContentHash([fingerprint; 32])),
+            ..DirMeta::default()
+        };
+        finder.add(&Event::DirFinish {
+            path: id(path),
+            size: 100,
+            num_files: 1,
+            meta: Some(Box::new(meta)),
+        });
+    }
+
+    /// Finds the identical subtrees, as the sorted ids of each group.
+    fn find_trees(finder: TreeDupeFinder) -> Vec<Vec<usize>> {
+        let groups = finder.find(|path| Some(Path::new(&path.into_raw().to_string()).into()));
+        let mut groups = groups
+            .into_iter()
+            .map(|group| {
+                let ids = group
+                    .paths
+                    .iter()
+                    .map(|path| path.0.to_str().unwrap().parse());
+                let mut ids = ids.collect::<Result<Vec<usize>, _>>().unwrap();
+                ids.sort();
+                ids
+            })
+            .collect::<Vec<_>>();
+        groups.sort();
+        groups
+    }
+
+    #[test]
+    fn omit_trees_covered_by_parents() {
+        let mut finder = TreeDupeFinder::default();
+        // The copies 10 and 20 of the same tree, whose children 11 and 21 are
+        // reported by them.
+        add_dir(&mut finder, 10, 1, 1);
+        add_dir(&mut finder, 20, 1, 1);
+        add_dir(&mut finder, 11, 10, 2);
+        add_dir(&mut finder, 21, 20, 2);
+        assert_eq!(find_trees(finder), [vec![10, 20]]);
+    }
+
+    #[test]
+    fn keep_trees_whose_parents_differ() {
+        let mut finder = TreeDupeFinder::default();
+        // 10 and 20 are copies, and 30 and 40 are copies of another tree,
+        // while 11 in 10 and 31 in 30 are copies only of each other.
+        add_dir(&mut finder, 10, 1, 1);
+        add_dir(&mut finder, 20, 1, 1);
+        add_dir(&mut finder, 30, 1, 3);
+        add_dir(&mut finder, 40, 1, 3);
+        add_dir(&mut finder, 11, 10, 2);
+        add_dir(&mut finder, 31, 30, 2);
+        assert_eq!(
+            find_trees(finder),
+            [vec![10, 20], vec![11, 31], vec![30, 40]]
+        );
+    }
+
+    #[test]
+    fn keep_trees_in_the_same_parent() {
+        let mut finder = TreeDupeFinder::default();
+        // 11 and 12 are copies in the same directory 10, which is a copy of
+        // 20 containing 21 and 22.
+        add_dir(&mut finder, 10, 1, 1);
+        add_dir(&mut finder, 20, 1, 1);
+        for (path, parent) in [(11, 10), (12, 10), (21, 20), (22, 20)] {
+            add_dir(&mut finder, path, parent, 2);
+        }
+        assert_eq!(find_trees(finder), [vec![10, 20], vec![11, 12, 21, 22]]);
+    }
+}
@@ -1,7 +1,7 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::{ImmutPath, PathId, ShrRx};
+use crate::{AgeBucket, AgeUsage, ImmutPath, Owner, OwnerUsage, PathId, ShrRx};
 
 /// A event reference yield by `shr`.
 #[derive(Debug)]
@@ -24,6 +24,11 @@ impl EventRef<'_> {
         self.data
     }
 
+    /// Gets the raw event.
+    pub fn as_raw(&self) -> &Event {
+        &self.data
+    }
+
     /// Collects path for display.
     pub fn display(&self) -> EventDisplay {
         match self.data {
@@ -31,19 +36,36 @@ impl EventRef<'_> {
                 path: self.rx.get_path(path).map(ImmutPath),
                 parent: parent.and_then(|parent| self.rx.get_path(parent).map(ImmutPath)),
             },
-            Event::FileFinish { path, parent, size } => EventDisplay::FileFinish {
+            Event::FileFinish {
+                path,
+                parent,
+                size,
+                ref meta,
+            } => EventDisplay::FileFinish {
                 path: self.rx.get_path(path).map(ImmutPath),
                 parent: parent.and_then(|parent| self.rx.get_path(parent).map(ImmutPath)),
                 size,
+                meta: meta.clone(),
             },
             Event::DirFinish {
                 path,
                 size,
                 num_files,
+                ref meta,
             } => EventDisplay::DirFinish {
                 path: self.rx.get_path(path).map(ImmutPath),
                 size,
                 num_files,
+                meta: meta.clone(),
+            },
+            Event::Total {
+                size,
+                num_files,
+                ref meta,
+            } => EventDisplay::Total {
+                size,
+                num_files,
+                meta: meta.clone(),
             },
         }
     }
@@ -65,7 +87,7 @@ pub type Event = EventModel<PathId, Option<PathId>>;
 pub type EventDisplay = EventModel<Option<ImmutPath>, Option<ImmutPath>>;
 
 /// A event yield by `shr`.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "camelCase"))]
 pub enum EventModel<ThisP, ParentP> {
@@ -84,6 +106,12 @@ pub enum EventModel<ThisP, ParentP> {
         parent: ParentP,
         /// The size of the file in bytes, recursively.
         size: u64,
+        /// The extra metadata of the file, if any is requested.
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "Option::is_none")
+        )]
+        meta: Option<Box<FileMeta>>,
     },
     /// A directory is finished.
     DirFinish {
@@ -93,5 +121,89 @@ pub enum EventModel<ThisP, ParentP> {
         size: u64,
         /// The number of files in the directory.
         num_files: usize,
+        /// The extra statistics of the directory, if any is requested.
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "Option::is_none")
+        )]
+        meta: Option<Box<DirMeta>>,
     },
+    /// All the roots are finished. This is the synthetic grand-total root of
+    /// the roots, whose parents are `None`.
+    Total {
+        /// The size of all the roots in bytes, recursively.
+        size: u64,
+        /// The number of files in all the roots.
+        num_files: usize,
+        /// The extra statistics of all the roots, if any is requested.
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "Option::is_none")
+        )]
+        meta: Option<Box<DirMeta>>,
+    },
+}
+
+/// The extra metadata of a file, collected when requested by [`crate::Shr`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileMeta {
+    /// The owner of the file.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub owner: Option<Owner>,
+    /// The last modification time in seconds since the unix epoch.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub modified: Option<u64>,
+    /// The last access time in seconds since the unix epoch.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub accessed: Option<u64>,
+    /// The age bucket of the file, by the timestamp requested.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub age: Option<AgeBucket>,
+    /// The content hash of the file.
+    #[cfg(feature = "hash")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub hash: Option<crate::ContentHash>,
+}
+
+/// The extra statistics of a directory, collected when requested by
+/// [`crate::Shr`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DirMeta {
+    /// The usage grouped by owners, recursively.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub owners: Option<OwnerUsage>,
+    /// The bytes grouped by age buckets, recursively.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub ages: Option<AgeUsage>,
+    /// The Merkle-style fingerprint of the directory, from the names, sizes
+    /// and content hashes of its children.
+    #[cfg(feature = "hash")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub fingerprint: Option<crate::ContentHash>,
 }
@@ -0,0 +1,202 @@
+//! Matches the paths against glob patterns, e.g. `**/*.log`.
+
+use std::path::{Component, Path, PathBuf};
+
+/// A glob pattern of paths.
+///
+/// - `*` matches any characters in a component, and `?` matches one.
+/// - `**` as a whole component matches any number of components.
+/// - A pattern without `/` matches the names at any depth, as in
+///   `.gitignore`, and a trailing `/` is ignored.
+///
+/// A relative pattern is matched against the paths relative to where it
+/// applies, e.g. the root of a scan.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    /// The pattern as given.
+    pattern: String,
+    /// Whether the pattern begins with `/`.
+    absolute: bool,
+    /// The components of the pattern.
+    components: Vec<String>,
+}
+
+impl Glob {
+    /// Parses a glob pattern.
+    pub fn new(pattern: &str) -> Self {
+        let absolute = pattern.starts_with('/');
+        let trimmed = pattern.trim_matches('/');
+        let mut components = trimmed
+            .split('/')
+            .filter(|c| !c.is_empty() && *c != ".")
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        if !absolute && !trimmed.contains('/') {
+            components.insert(0, "**".to_owned());
+        }
+        Self {
+            pattern: pattern.to_owned(),
+            absolute,
+            components,
+        }
+    }
+
+    /// Makes a relative pattern match at any depth, as if it began with
+    /// `**/`.
+    pub fn anywhere(mut self) -> Self {
+        if !self.absolute && self.components.first().is_none_or(|c| c != "**") {
+            self.components.insert(0, "**".to_owned());
+        }
+        self
+    }
+
+    /// Whether the string has any wildcard, otherwise it is a plain path.
+    pub fn is_glob(s: &str) -> bool {
+        s.contains(['*', '?'])
+    }
+
+    /// Gets the pattern as given.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Gets the leading components without wildcards, which contain all the
+    /// paths matched.
+    pub fn base(&self) -> PathBuf {
+        let mut base = PathBuf::from(if self.absolute { "/" } else { "" });
+        for component in &self.components {
+            if Self::is_glob(component) {
+                break;
+            }
+            base.push(component);
+        }
+        base
+    }
+
+    /// Whether the path matches the pattern.
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.absolute != path.has_root() {
+            return false;
+        }
+        let names = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let names = names.iter().map(|name| name.as_ref()).collect::<Vec<_>>();
+        match_components(&self.components, &names)
+    }
+}
+
+/// Matches the components, where `**` matches any number of them.
+fn match_components(pattern: &[String], names: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => names.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=names.len()).any(|skip| match_components(rest, &names[skip..]))
+        }
+        Some((first, rest)) => match names.split_first() {
+            Some((name, names)) => match_name(first, name) && match_components(rest, names),
+            None => false,
+        },
+    }
+}
+
+/// Matches a component, where `*` matches any characters and `?` matches one.
+fn match_name(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // The position after the last `*`, and the name position it resumes at.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some('?') => (p, n) = (p + 1, n + 1),
+            Some(c) if *c == name[n] => (p, n) = (p + 1, n + 1),
+            _ => match star {
+                Some((after, resume)) => {
+                    (p, n) = (after, resume + 1);
+                    star = Some((after, resume + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).matches(Path::new(path))
+    }
+
+    #[test]
+    fn match_names() {
+        assert!(match_name("*.log", "a.log"));
+        assert!(match_name("*.log", ".log"));
+        assert!(!match_name("*.log", "a.log.gz"));
+        assert!(match_name("a*b*c", "abbbc"));
+        assert!(match_name("a*b*c", "axbxbxc"));
+        assert!(!match_name("a*b*c", "axbx"));
+        assert!(match_name("?.rs", "a.rs"));
+        assert!(!match_name("?.rs", "ab.rs"));
+        assert!(match_name("*", ""));
+        assert!(!match_name("?", ""));
+    }
+
+    #[test]
+    fn match_any_depth() {
+        // A pattern without `/` matches the names at any depth.
+        assert!(matches("*.log", "a.log"));
+        assert!(matches("*.log", "x/y/a.log"));
+        assert!(matches("node_modules/", "a/node_modules"));
+        assert!(!matches("*.log", "a.log/b"));
+    }
+
+    #[test]
+    fn match_double_star() {
+        assert!(matches("**/*.log", "a.log"));
+        assert!(matches("**/*.log", "x/y/a.log"));
+        assert!(matches("src/**/mod.rs", "src/mod.rs"));
+        assert!(matches("src/**/mod.rs", "src/a/b/mod.rs"));
+        assert!(!matches("src/**/mod.rs", "lib/src/a/mod.rs"));
+        assert!(matches("target/**", "target/debug/x"));
+    }
+
+    #[test]
+    fn match_anchored() {
+        // A pattern with `/` is anchored at the start.
+        assert!(matches("target/debug", "target/debug"));
+        assert!(matches("./target/debug/", "target/debug"));
+        assert!(!matches("target/debug", "a/target/debug"));
+        assert!(!matches("target/debug", "target/debug/x"));
+        assert!(
+            Glob::new("target/debug")
+                .anywhere()
+                .matches(Path::new("a/target/debug"))
+        );
+
+        // An absolute pattern only matches the absolute paths.
+        assert!(matches("/tmp/*.log", "/tmp/a.log"));
+        assert!(!matches("/tmp/*.log", "tmp/a.log"));
+        assert!(!matches("tmp/*.log", "/tmp/a.log"));
+        assert!(!Glob::new("/tmp").anywhere().matches(Path::new("/a/tmp")));
+    }
+
+    #[test]
+    fn glob_base() {
+        assert_eq!(Glob::new("src/**/*.rs").base(), Path::new("src"));
+        assert_eq!(Glob::new("/var/log/*.log").base(), Path::new("/var/log"));
+        assert_eq!(Glob::new("*.log").base(), Path::new(""));
+        assert!(Glob::is_glob("a?") && Glob::is_glob("*") && !Glob::is_glob("a/b"));
+    }
+}
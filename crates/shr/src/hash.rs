@@ -0,0 +1,223 @@
+//! Content hashes of files and fingerprints of directories.
+
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The bytes read to compute the partial hash of a file.
+pub(crate) const PARTIAL_SIZE: u64 = 4096;
+
+/// A content hash of a file, or a fingerprint of a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentHash(pub [u8; 32]);
+
+impl ContentHash {
+    /// Hashes the whole file.
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut hasher = blake3::Hasher::new();
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+        Ok(Self(*hasher.finalize().as_bytes()))
+    }
+
+    /// Hashes the first `PARTIAL_SIZE` bytes of the file.
+    pub(crate) fn of_file_head(path: &Path) -> io::Result<Self> {
+        let mut hasher = blake3::Hasher::new();
+        io::copy(&mut File::open(path)?.take(PARTIAL_SIZE), &mut hasher)?;
+        Ok(Self(*hasher.finalize().as_bytes()))
+    }
+
+    /// Converts to a hex string.
+    pub fn to_hex(&self) -> String {
+        blake3::Hash::from_bytes(self.0).to_hex().to_string()
+    }
+
+    /// Parses from a hex string.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        blake3::Hash::from_hex(hex)
+            .ok()
+            .map(|hash| Self(*hash.as_bytes()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ContentHash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.to_hex().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for ContentHash {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        Self::from_hex(&hex).ok_or_else(|| serde::de::Error::custom("invalid content hash"))
+    }
+}
+
+/// A child of a directory that contributes to its fingerprint.
+#[derive(Debug, Clone)]
+pub(crate) struct FingerprintEntry {
+    /// The name of the child.
+    pub name: Box<OsStr>,
+    /// The size of the child in bytes, recursively.
+    pub size: u64,
+    /// The content hash of the file or the fingerprint of the directory.
+    pub hash: ContentHash,
+}
+
+/// Computes the Merkle-style fingerprint of a directory from its children,
+/// which is independent of the order of scanning.
+pub(crate) fn fingerprint(mut entries: Vec<FingerprintEntry>) -> ContentHash {
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut hasher = blake3::Hasher::new();
+    for entry in &entries {
+        let name = entry.name.as_encoded_bytes();
+        hasher.update(&(name.len() as u64).to_le_bytes());
+        hasher.update(name);
+        hasher.update(&entry.size.to_le_bytes());
+        hasher.update(&entry.hash.0);
+    }
+    ContentHash(*hasher.finalize().as_bytes())
+}
+
+/// The state to compute the fingerprint of an entry during the scan.
+#[derive(Debug, Default)]
+pub(crate) struct FingerprintState {
+    /// The content hash of the file or the fingerprint of the directory.
+    pub own: Option<ContentHash>,
+    /// The children adopted so far.
+    children: Vec<FingerprintEntry>,
+    /// Whether some child cannot be hashed, so the fingerprint is unknown.
+    incomplete: bool,
+}
+
+impl FingerprintState {
+    /// Creates the state of a file.
+    pub fn file(hash: Option<ContentHash>) -> Self {
+        Self {
+            own: hash,
+            ..Self::default()
+        }
+    }
+
+    /// Turns the state of an entry into the contribution to its parent.
+    pub fn adopt(&mut self, name: &OsStr, size: u64) {
+        match self.own.take() {
+            Some(hash) => self.children.push(FingerprintEntry {
+                name: name.into(),
+                size,
+                hash,
+            }),
+            None => self.incomplete = true,
+        }
+    }
+
+    /// Marks the fingerprint as unknown, since some children cannot be read.
+    pub fn invalidate(&mut self) {
+        self.incomplete = true;
+    }
+
+    /// Merges the contributions of siblings.
+    pub fn merge(&mut self, other: Self) {
+        self.children.extend(other.children);
+        self.incomplete |= other.incomplete;
+    }
+
+    /// Computes the fingerprint of a directory from the adopted children.
+    pub fn finish(&mut self) {
+        let children = std::mem::take(&mut self.children);
+        self.own = (!self.incomplete).then(|| fingerprint(children));
+        self.incomplete = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64, hash: u8) -> FingerprintEntry {
+        FingerprintEntry {
+            name: OsStr::new(name).into(),
+            size,
+            hash: ContentHash([hash; 32]),
+        }
+    }
+
+    #[test]
+    fn hash_files() {
+        let dir = std::env::temp_dir().join(format!("shr-hash-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (small, large) = (dir.join("small"), dir.join("large"));
+        std::fs::write(&small, b"content").unwrap();
+        std::fs::write(&large, vec![1; PARTIAL_SIZE as usize + 1]).unwrap();
+        let hashes = [
+            ContentHash::of_file(&small).unwrap(),
+            ContentHash::of_file_head(&small).unwrap(),
+            ContentHash::of_file(&large).unwrap(),
+            ContentHash::of_file_head(&large).unwrap(),
+        ];
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The head of a small file is the whole file.
+        assert_eq!(hashes[0], hashes[1]);
+        assert_ne!(hashes[2], hashes[3]);
+        let head = blake3::hash(&[1; PARTIAL_SIZE as usize]);
+        assert_eq!(hashes[3].0, *head.as_bytes());
+
+        let hex = hashes[0].to_hex();
+        assert_eq!(ContentHash::from_hex(&hex), Some(hashes[0]));
+        assert_eq!(ContentHash::from_hex("not hex"), None);
+    }
+
+    #[test]
+    fn fingerprint_ignores_order() {
+        let a = fingerprint(vec![entry("a", 1, 1), entry("b", 2, 2)]);
+        let b = fingerprint(vec![entry("b", 2, 2), entry("a", 1, 1)]);
+        assert_eq!(a, b);
+        // The names, sizes and hashes all contribute.
+        assert_ne!(a, fingerprint(vec![entry("a", 1, 1), entry("c", 2, 2)]));
+        assert_ne!(a, fingerprint(vec![entry("a", 1, 1), entry("b", 3, 2)]));
+        assert_ne!(a, fingerprint(vec![entry("a", 1, 1), entry("b", 2, 3)]));
+        // The names are delimited, so `ab` + `c` is not `a` + `bc`.
+        assert_ne!(
+            fingerprint(vec![entry("ab", 0, 0), entry("c", 0, 0)]),
+            fingerprint(vec![entry("a", 0, 0), entry("bc", 0, 0)])
+        );
+    }
+
+    #[test]
+    fn fingerprint_state() {
+        let mut dir = FingerprintState::default();
+        let mut file = FingerprintState::file(Some(ContentHash([1; 32])));
+        file.adopt(OsStr::new("f"), 1);
+        dir.merge(file);
+        dir.finish();
+        assert_eq!(dir.own, Some(fingerprint(vec![entry("f", 1, 1)])));
+
+        // A child without a hash makes the fingerprint unknown.
+        let mut dir = FingerprintState::default();
+        let mut file = FingerprintState::file(None);
+        file.adopt(OsStr::new("f"), 1);
+        dir.merge(file);
+        dir.finish();
+        assert_eq!(dir.own, None);
+
+        let mut dir = FingerprintState::default();
+        dir.invalidate();
+        dir.finish();
+        assert_eq!(dir.own, None);
+    }
+}
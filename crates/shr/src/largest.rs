@@ -0,0 +1,165 @@
+//! The largest files and leaf directories found during a scan.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::PathId;
+
+/// The largest files and leaf directories (directories without
+/// subdirectories), maintained in bounded top-K heaps during the scan.
+#[derive(Debug)]
+pub struct Largest {
+    /// The largest files.
+    files: TopK,
+    /// The largest leaf directories.
+    dirs: TopK,
+}
+
+impl Largest {
+    /// Creates the heaps keeping at most `k` entries.
+    pub(crate) fn new(k: usize) -> Self {
+        Self {
+            files: TopK::new(k),
+            dirs: TopK::new(k),
+        }
+    }
+
+    /// Adds a file.
+    pub(crate) fn add_file(&self, path: PathId, size: u64) {
+        self.files.add(path, size);
+    }
+
+    /// Adds a leaf directory.
+    pub(crate) fn add_dir(&self, path: PathId, size: u64) {
+        self.dirs.add(path, size);
+    }
+
+    /// Gets the largest files found so far, from the largest.
+    pub fn files(&self) -> Vec<(PathId, u64)> {
+        self.files.sorted()
+    }
+
+    /// Gets the largest leaf directories found so far, from the largest.
+    pub fn dirs(&self) -> Vec<(PathId, u64)> {
+        self.dirs.sorted()
+    }
+}
+
+/// A bounded min-heap keeping the `k` largest entries.
+#[derive(Debug)]
+struct TopK {
+    k: usize,
+    heap: Mutex<BinaryHeap<Reverse<(u64, PathId)>>>,
+    /// The smallest size in the heap once it is full, so that most of the
+    /// entries are rejected without locking.
+    threshold: AtomicU64,
+}
+
+impl TopK {
+    fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: Mutex::new(BinaryHeap::with_capacity(k + 1)),
+            threshold: AtomicU64::new(0),
+        }
+    }
+
+    fn add(&self, path: PathId, size: u64) {
+        if self.k == 0 || size < self.threshold.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut heap = self.heap.lock().unwrap();
+        heap.push(Reverse((size, path)));
+        if heap.len() > self.k {
+            heap.pop();
+        }
+        if heap.len() == self.k {
+            if let Some(Reverse((min, _))) = heap.peek() {
+                self.threshold.store(*min, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn sorted(&self) -> Vec<(PathId, u64)> {
+        let heap = self.heap.lock().unwrap();
+        let mut entries = heap
+            .iter()
+            .map(|Reverse((size, path))| (*path, *size))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(_, size)| Reverse(*size));
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+
+    fn id(n: usize) -> PathId {
+        PathId::from_raw(NonZeroUsize::new(n).unwrap())
+    }
+
+    #[test]
+    fn evict_the_smallest() {
+        let top = TopK::new(3);
+        for (n, size) in [(1, 10), (2, 50), (3, 20), (4, 40), (5, 30)] {
+            top.add(id(n), size);
+        }
+        assert_eq!(top.sorted(), vec![(id(2), 50), (id(4), 40), (id(5), 30)]);
+        assert_eq!(top.threshold.load(Ordering::Relaxed), 30);
+    }
+
+    #[test]
+    fn threshold_once_full() {
+        let top = TopK::new(2);
+        top.add(id(1), 10);
+        assert_eq!(top.threshold.load(Ordering::Relaxed), 0);
+        top.add(id(2), 5);
+        assert_eq!(top.threshold.load(Ordering::Relaxed), 5);
+
+        // The entries below the threshold are rejected without locking.
+        top.add(id(3), 4);
+        assert_eq!(top.heap.lock().unwrap().len(), 2);
+        top.add(id(4), 7);
+        assert_eq!(top.threshold.load(Ordering::Relaxed), 7);
+        assert_eq!(top.sorted(), vec![(id(1), 10), (id(4), 7)]);
+    }
+
+    #[test]
+    fn keep_nothing() {
+        let top = TopK::new(0);
+        top.add(id(1), 10);
+        assert!(top.sorted().is_empty());
+    }
+
+    #[test]
+    fn add_concurrently() {
+        let largest = Largest::new(10);
+        std::thread::scope(|scope| {
+            for t in 0..4 {
+                let largest = &largest;
+                scope.spawn(move || {
+                    for n in 0..1000 {
+                        let n = t * 1000 + n + 1;
+                        largest.add_file(id(n), n as u64);
+                    }
+                });
+            }
+        });
+        let sizes = largest.files().into_iter().map(|(_, size)| size);
+        assert_eq!(
+            sizes.collect::<Vec<_>>(),
+            (3991..=4000).rev().collect::<Vec<_>>()
+        );
+        assert!(largest.dirs().is_empty());
+    }
+}
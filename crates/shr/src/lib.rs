@@ -4,13 +4,49 @@
 
 pub mod utils;
 
+pub use age::*;
+#[cfg(feature = "agent")]
+pub use agent::*;
+#[cfg(feature = "config")]
+pub use config::*;
+#[cfg(feature = "hash")]
+pub use dupes::*;
 pub use event::*;
+pub use glob::*;
+#[cfg(feature = "hash")]
+pub use hash::*;
+pub use largest::*;
+#[cfg(feature = "ncdu")]
+pub use ncdu::*;
+pub use owner::*;
 pub use path::*;
+#[cfg(feature = "replay")]
+pub use replay::*;
 pub use scan::*;
+#[cfg(feature = "serde")]
+pub use stream::*;
 
+mod age;
+#[cfg(feature = "agent")]
+mod agent;
+#[cfg(feature = "config")]
+mod config;
+#[cfg(feature = "hash")]
+mod dupes;
 mod event;
+mod glob;
+#[cfg(feature = "hash")]
+mod hash;
+mod largest;
+#[cfg(feature = "ncdu")]
+mod ncdu;
+mod owner;
 mod path;
+#[cfg(feature = "replay")]
+mod replay;
 mod scan;
+#[cfg(feature = "serde")]
+mod stream;
 
 /// A shortcut to run the [`Shr`].
 pub async fn shr(dir: std::path::PathBuf) -> ShrRx {
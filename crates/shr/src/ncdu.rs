@@ -0,0 +1,408 @@
+//! Loads the JSON export of [ncdu](https://dev.yorhel.nl/ncdu/jsonfmt).
+//!
+//! The export is an array of `[major, minor, metadata, root]`, where a
+//! directory is an array whose first element is the information of the
+//! directory itself, followed by its children, and a file is an object.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor},
+};
+
+use crate::{Event, PathId, ShrRx, ShrTx};
+
+/// The major version of the ncdu export format.
+pub const NCDU_MAJOR: u64 = 1;
+
+/// Loads an ncdu export as if the root directory were scanned. The sizes are
+/// the apparent sizes (`asize`) if present, otherwise the disk usages
+/// (`dsize`).
+///
+/// The names are read as raw bytes, since ncdu writes the names which are not
+/// valid UTF-8 as they are. The hard links to the same inode (`hlnkc` or
+/// `nlink`) are counted once, and the entries which ncdu failed to read
+/// (`read_error`) or excluded (`excluded`) are skipped like an unreadable or
+/// excluded entry in a scan.
+pub fn load_ncdu(reader: impl Read, largest: Option<usize>) -> anyhow::Result<ShrRx> {
+    let (major, _minor, _meta, root): (u64, IgnoredAny, IgnoredAny, Entry) =
+        serde_json::from_reader(reader).context("failed to parse ncdu export")?;
+    if major != NCDU_MAJOR {
+        bail!("unsupported ncdu export version: {major}");
+    }
+
+    let (mut tx, rx) = ShrTx::channel(largest);
+    let Entry::Dir(info, children) = root else {
+        bail!("invalid ncdu export: the root is not a directory");
+    };
+
+    /// A directory whose children are being loaded.
+    struct Frame {
+        id: PathId,
+        path: PathBuf,
+        /// The device of the entries, which ncdu only writes if it differs
+        /// from the parent's.
+        dev: u64,
+        children: std::vec::IntoIter<Entry>,
+        size: u64,
+        num_files: usize,
+    }
+
+    let path = PathBuf::from(info.name);
+    let id = tx.intern(&path);
+    tx.send(Event::Dir {
+        path: id,
+        parent: None,
+    });
+    // The inodes of the hard links counted so far, by devices.
+    let mut links = HashSet::<(u64, u64)>::new();
+    let mut stack = vec![Frame {
+        id,
+        path,
+        dev: info.dev.unwrap_or(0),
+        children: children.into_iter(),
+        size: 0,
+        num_files: 0,
+    }];
+
+    // Walks the tree without recursion, since the exports can be deep.
+    while let Some(frame) = stack.last_mut() {
+        let Some(child) = frame.children.next() else {
+            let frame = stack.pop().unwrap();
+            tx.send(Event::DirFinish {
+                path: frame.id,
+                size: frame.size,
+                num_files: frame.num_files,
+                meta: None,
+            });
+            match stack.last_mut() {
+                Some(parent) => {
+                    parent.size += frame.size;
+                    parent.num_files += frame.num_files;
+                }
+                None => {
+                    tx.send(Event::Total {
+                        size: frame.size,
+                        num_files: frame.num_files,
+                        meta: None,
+                    });
+                }
+            }
+            continue;
+        };
+
+        match child {
+            Entry::Dir(info, children) => {
+                let path = frame.path.join(info.name);
+                let id = tx.intern(&path);
+                tx.send(Event::Dir {
+                    path: id,
+                    parent: Some(frame.id),
+                });
+                let dev = info.dev.unwrap_or(frame.dev);
+                stack.push(Frame {
+                    id,
+                    path,
+                    dev,
+                    children: children.into_iter(),
+                    size: 0,
+                    num_files: 0,
+                });
+            }
+            Entry::File(info) if info.read_error || info.excluded => {}
+            Entry::File(info) => {
+                let path = frame.path.join(&info.name);
+                let dev = info.dev.unwrap_or(frame.dev);
+                let counted = match info.ino.filter(|_| info.linked) {
+                    Some(ino) => !links.insert((dev, ino)),
+                    None => false,
+                };
+                let size = if counted { 0 } else { info.size };
+                tx.send(Event::FileFinish {
+                    path: tx.intern(&path),
+                    parent: Some(frame.id),
+                    size,
+                    meta: None,
+                });
+                frame.size += size;
+                frame.num_files += 1;
+            }
+        }
+    }
+
+    Ok(rx)
+}
+
+/// An entry of the export.
+enum Entry {
+    /// A file, which is an object.
+    File(Info),
+    /// A directory, which is an array of its information and its children.
+    Dir(Info, Vec<Entry>),
+}
+
+/// The information of an entry.
+struct Info {
+    /// The name, which is the full path for the root.
+    name: OsString,
+    /// The size in bytes.
+    size: u64,
+    /// The device, if it differs from the parent's.
+    dev: Option<u64>,
+    /// The inode.
+    ino: Option<u64>,
+    /// Whether the file has other hard links.
+    linked: bool,
+    /// Whether ncdu failed to read the entry.
+    read_error: bool,
+    /// Whether ncdu excluded the entry, e.g. by a pattern.
+    excluded: bool,
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = Entry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an ncdu entry")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Entry, A::Error> {
+                let info = seq
+                    .next_element::<Info>()?
+                    .ok_or_else(|| de::Error::custom("empty directory"))?;
+                let mut children = Vec::new();
+                while let Some(child) = seq.next_element()? {
+                    children.push(child);
+                }
+                Ok(Entry::Dir(info, children))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Entry, A::Error> {
+                InfoVisitor.visit_map(map).map(Entry::File)
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+/// Visits the information of an entry.
+struct InfoVisitor;
+
+impl<'de> Visitor<'de> for InfoVisitor {
+    type Value = Info;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the information of an ncdu entry")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Info, A::Error> {
+        let (mut name, mut asize, mut dsize) = (None, None, None);
+        let (mut dev, mut ino, mut nlink) = (None, None, None);
+        let (mut hlnkc, mut read_error, mut excluded) = (false, false, false);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "name" => name = Some(map.next_value::<Name>()?.0),
+                "asize" => asize = Some(map.next_value()?),
+                "dsize" => dsize = Some(map.next_value()?),
+                "dev" => dev = Some(map.next_value()?),
+                "ino" => ino = Some(map.next_value()?),
+                "nlink" => nlink = Some(map.next_value::<u64>()?),
+                "hlnkc" => hlnkc = map.next_value::<Flag>()?.0,
+                "read_error" => read_error = map.next_value::<Flag>()?.0,
+                "excluded" => excluded = map.next_value::<Flag>()?.0,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Info {
+            name: name.ok_or_else(|| de::Error::custom("an entry has no name"))?,
+            size: asize.or(dsize).unwrap_or(0),
+            dev,
+            ino,
+            linked: hlnkc || nlink.is_some_and(|nlink| nlink > 1),
+            read_error,
+            excluded,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Info {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(InfoVisitor)
+    }
+}
+
+/// A flag which is set by `true` or any string, e.g. `"excluded":"pattern"`.
+struct Flag(bool);
+
+impl<'de> Deserialize<'de> for Flag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FlagVisitor;
+
+        impl Visitor<'_> for FlagVisitor {
+            type Value = Flag;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a flag")
+            }
+
+            fn visit_bool<E: de::Error>(self, flag: bool) -> Result<Flag, E> {
+                Ok(Flag(flag))
+            }
+
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<Flag, E> {
+                Ok(Flag(true))
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Flag, E> {
+                Ok(Flag(false))
+            }
+        }
+
+        deserializer.deserialize_any(FlagVisitor)
+    }
+}
+
+/// A name read as raw bytes.
+struct Name(OsString);
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NameVisitor;
+
+        impl Visitor<'_> for NameVisitor {
+            type Value = Name;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a name")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Name, E> {
+                Ok(Name(name.into()))
+            }
+
+            fn visit_bytes<E: de::Error>(self, name: &[u8]) -> Result<Name, E> {
+                Ok(Name(os_string(name)))
+            }
+        }
+
+        deserializer.deserialize_bytes(NameVisitor)
+    }
+}
+
+/// Converts the raw bytes of a name.
+fn os_string(name: &[u8]) -> OsString {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        std::ffi::OsStr::from_bytes(name).to_owned()
+    }
+    #[cfg(not(unix))]
+    {
+        // The raw bytes are only meaningful on unix, so the rest is replaced.
+        String::from_utf8_lossy(name).into_owned().into()
+    }
+}
+
+/// Writes a name as ncdu does: a JSON string escaping the quotes, the
+/// backslashes and the control characters, and keeping the other bytes as they
+/// are, even if they are not valid UTF-8.
+pub fn write_ncdu_name(w: &mut impl std::io::Write, name: &Path) -> std::io::Result<()> {
+    w.write_all(b"\"")?;
+    for &byte in name.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'"' => w.write_all(b"\\\"")?,
+            b'\\' => w.write_all(b"\\\\")?,
+            b'\n' => w.write_all(b"\\n")?,
+            b'\t' => w.write_all(b"\\t")?,
+            0..0x20 | 0x7F => write!(w, "\\u{byte:04x}")?,
+            byte => w.write_all(&[byte])?,
+        }
+    }
+    w.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads the export, and collects the sizes and the numbers of files of
+    /// the directories and the files by names.
+    async fn load(tree: &str) -> Vec<(String, u64, usize)> {
+        let export = format!("[1,2,{{}},{tree}]");
+        let mut rx = load_ncdu(export.as_bytes(), None).unwrap();
+        let mut entries = Vec::new();
+        while let Some(event) = rx.recv().await {
+            let (path, size, num_files) = match event.to_raw() {
+                Event::FileFinish { path, size, .. } => (path, size, 1),
+                Event::DirFinish {
+                    path,
+                    size,
+                    num_files,
+                    ..
+                } => (path, size, num_files),
+                _ => continue,
+            };
+            let path = rx.get_path(path).unwrap();
+            entries.push((path.to_string_lossy().into_owned(), size, num_files));
+        }
+        entries.sort();
+        entries
+    }
+
+    #[tokio::test]
+    async fn count_hard_links_once() {
+        let tree = concat!(
+            r#"[{"name":"/r","dev":1},"#,
+            r#"{"name":"a","asize":10,"ino":7,"hlnkc":true},"#,
+            r#"[{"name":"d"},{"name":"b","asize":10,"ino":7,"hlnkc":true}],"#,
+            // The same inode on another device is another file.
+            r#"[{"name":"m","dev":2},{"name":"c","asize":10,"ino":7,"nlink":2}],"#,
+            r#"{"name":"e","asize":5,"ino":8}]"#
+        );
+        assert_eq!(
+            load(tree).await,
+            [
+                ("/r".to_owned(), 25, 4),
+                ("/r/a".to_owned(), 10, 1),
+                ("/r/d".to_owned(), 0, 1),
+                ("/r/d/b".to_owned(), 0, 1),
+                ("/r/e".to_owned(), 5, 1),
+                ("/r/m".to_owned(), 10, 1),
+                ("/r/m/c".to_owned(), 10, 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn skip_unread_and_excluded() {
+        let tree = concat!(
+            r#"[{"name":"/r"},{"name":"a","asize":1},"#,
+            r#"{"name":"b","asize":2,"read_error":true},"#,
+            r#"{"name":"c","excluded":"pattern"},"#,
+            r#"[{"name":"d","read_error":true}]]"#
+        );
+        assert_eq!(
+            load(tree).await,
+            [
+                ("/r".to_owned(), 1, 1),
+                ("/r/a".to_owned(), 1, 1),
+                ("/r/d".to_owned(), 0, 0),
+            ]
+        );
+    }
+}
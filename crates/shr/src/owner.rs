@@ -0,0 +1,215 @@
+//! Usage breakdown by owner (uid/gid).
+
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The owner of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Owner {
+    /// The user id.
+    pub uid: u32,
+    /// The group id.
+    pub gid: u32,
+}
+
+impl Owner {
+    /// Gets the owner from the metadata, which is only available on unix.
+    pub(crate) fn from_metadata(_mt: &std::fs::Metadata) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Some(Self {
+                uid: _mt.uid(),
+                gid: _mt.gid(),
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+}
+
+/// The bytes and files used by an owner.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Usage {
+    /// The size in bytes.
+    pub size: u64,
+    /// The number of files.
+    pub num_files: usize,
+}
+
+impl Usage {
+    /// Adds another usage to this one.
+    pub fn add(&mut self, other: Usage) {
+        self.size += other.size;
+        self.num_files += other.num_files;
+    }
+}
+
+/// The bytes and files of a directory, grouped by uid and gid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnerUsage {
+    /// The usage grouped by user id.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_id_map"))]
+    pub users: BTreeMap<u32, Usage>,
+    /// The usage grouped by group id.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_id_map"))]
+    pub groups: BTreeMap<u32, Usage>,
+}
+
+/// Deserializes a map keyed by ids. The keys are strings in JSON, which cannot
+/// be parsed as integers when the map is buffered, e.g. in a tagged enum.
+#[cfg(feature = "serde")]
+fn deserialize_id_map<'de, D>(deserializer: D) -> Result<BTreeMap<u32, Usage>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+    #[serde(untagged)]
+    enum Id {
+        Num(u32),
+        Str(String),
+    }
+
+    BTreeMap::<Id, Usage>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(id, usage)| match id {
+            Id::Num(id) => Ok((id, usage)),
+            Id::Str(id) => id
+                .parse()
+                .map(|id| (id, usage))
+                .map_err(serde::de::Error::custom),
+        })
+        .collect()
+}
+
+impl OwnerUsage {
+    /// Accounts a file owned by `owner`.
+    pub fn add_file(&mut self, owner: Owner, size: u64) {
+        let usage = Usage { size, num_files: 1 };
+        self.users.entry(owner.uid).or_default().add(usage);
+        self.groups.entry(owner.gid).or_default().add(usage);
+    }
+
+    /// Merges the usage of another directory into this one.
+    pub fn merge(&mut self, other: &OwnerUsage) {
+        for (uid, usage) in &other.users {
+            self.users.entry(*uid).or_default().add(*usage);
+        }
+        for (gid, usage) in &other.groups {
+            self.groups.entry(*gid).or_default().add(*usage);
+        }
+    }
+
+    /// Gets the user that uses the most bytes.
+    pub fn top_user(&self) -> Option<(u32, Usage)> {
+        let (uid, usage) = self.users.iter().max_by_key(|(_, usage)| usage.size)?;
+        Some((*uid, *usage))
+    }
+}
+
+/// The user and group names resolved from the local passwd/group databases.
+#[derive(Debug, Default)]
+pub struct OwnerNames {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl OwnerNames {
+    /// Loads the names from `/etc/passwd` and `/etc/group`. The missing
+    /// databases are treated as empty.
+    pub fn load() -> Self {
+        let read = |path: &str| std::fs::read_to_string(path).unwrap_or_default();
+        Self {
+            users: parse_id_database(&read("/etc/passwd")),
+            groups: parse_id_database(&read("/etc/group")),
+        }
+    }
+
+    /// Gets the name of the user.
+    pub fn user(&self, uid: u32) -> Option<&str> {
+        self.users.get(&uid).map(String::as_str)
+    }
+
+    /// Gets the name of the group.
+    pub fn group(&self, gid: u32) -> Option<&str> {
+        self.groups.get(&gid).map(String::as_str)
+    }
+
+    /// Displays the user, falling back to the numeric id.
+    pub fn display_user(&self, uid: u32) -> String {
+        self.user(uid)
+            .map_or_else(|| uid.to_string(), str::to_owned)
+    }
+
+    /// Displays the group, falling back to the numeric id.
+    pub fn display_group(&self, gid: u32) -> String {
+        self.group(gid)
+            .map_or_else(|| gid.to_string(), str::to_owned)
+    }
+}
+
+/// Parses the `name:password:id:...` lines shared by passwd and group files.
+/// The comments and malformed lines are skipped, and the first name of an id
+/// wins as in `getpwuid`.
+fn parse_id_database(content: &str) -> HashMap<u32, String> {
+    let mut names = HashMap::new();
+    let entries = content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next().filter(|name| !name.is_empty())?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name))
+        });
+    for (id, name) in entries {
+        names.entry(id).or_insert_with(|| name.to_owned());
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_passwd() {
+        let names = parse_id_database(
+            "# comment:x:7\n\
+             root:x:0:0:root:/root:/bin/bash\n\
+             \n\
+             \t# indented:x:8\n\
+             user:x:1000:1000::/home/user:/bin/sh\n\
+             short:x\n\
+             nan:x:abc:1\n\
+             negative:x:-1:1\n\
+             :x:9:9\n\
+             alias:x:0:0\n\
+             group:x:100:user,other",
+        );
+        let mut names = names.into_iter().collect::<Vec<_>>();
+        names.sort();
+        let expected = [(0, "root"), (100, "group"), (1000, "user")];
+        let expected = expected.map(|(id, name)| (id, name.to_owned()));
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn resolve_names() {
+        let names = OwnerNames {
+            users: parse_id_database("root:x:0:0"),
+            groups: parse_id_database("wheel:x:10:root"),
+        };
+        assert_eq!(names.user(0), Some("root"));
+        assert_eq!(names.display_user(1), "1");
+        assert_eq!(names.display_group(10), "wheel");
+        assert_eq!(names.group(0), None);
+    }
+}
@@ -1,4 +1,6 @@
 use std::{
+    borrow::Cow,
+    fmt::Write,
     num::NonZeroUsize,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
@@ -9,7 +11,7 @@ use indexmap::IndexSet;
 use serde::{Deserialize, Serialize};
 
 /// A path id that is used to identify a path.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PathId(NonZeroUsize);
 
@@ -26,9 +28,21 @@ impl PathId {
 }
 
 /// A immutable path reference which can be serialized.
-#[derive(Debug)]
+///
+/// The path is serialized as a string if it is valid UTF-8. Otherwise, it is
+/// serialized losslessly as `{ "lossy": "...", "bytes": [...] }`, where the
+/// `lossy` is the [`display_path`] and the `bytes` are in the encoding of
+/// [`std::ffi::OsStr::as_encoded_bytes`].
+#[derive(Debug, Clone)]
 pub struct ImmutPath(pub Arc<Path>);
 
+impl ImmutPath {
+    /// Displays the path for humans, see [`display_path`].
+    pub fn display(&self) -> Cow<'_, str> {
+        display_path(&self.0)
+    }
+}
+
 impl AsRef<Arc<Path>> for ImmutPath {
     fn as_ref(&self) -> &Arc<Path> {
         &self.0
@@ -41,10 +55,85 @@ impl serde::Serialize for ImmutPath {
     where
         S: serde::Serializer,
     {
-        self.0.as_ref().serialize(serializer)
+        match self.0.to_str() {
+            Some(path) => path.serialize(serializer),
+            None => RawPath {
+                lossy: display_path(&self.0).into_owned(),
+                bytes: self.0.as_os_str().as_encoded_bytes().to_vec(),
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ImmutPath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Str(String),
+            Raw(RawPath),
+        }
+
+        let path = match Repr::deserialize(deserializer)? {
+            Repr::Str(path) => PathBuf::from(path),
+            Repr::Raw(raw) => raw.into_path(),
+        };
+        Ok(Self(path.into()))
     }
 }
 
+/// The serialized form of a path that is not valid UTF-8.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct RawPath {
+    /// The path for humans.
+    lossy: String,
+    /// The bytes of the path.
+    bytes: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl RawPath {
+    fn into_path(self) -> PathBuf {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStringExt;
+            std::ffi::OsString::from_vec(self.bytes).into()
+        }
+        #[cfg(not(unix))]
+        {
+            // The encoded bytes are only valid on the same platform, so only
+            // the valid UTF-8 is kept.
+            match String::from_utf8(self.bytes) {
+                Ok(path) => path.into(),
+                Err(_) => self.lossy.into(),
+            }
+        }
+    }
+}
+
+/// Displays a path for humans. The bytes that are not valid UTF-8 are escaped
+/// as `\xNN`, so that they are marked instead of being replaced silently.
+pub fn display_path(path: &Path) -> Cow<'_, str> {
+    if let Some(path) = path.to_str() {
+        return Cow::Borrowed(path);
+    }
+
+    let mut display = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        display.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            let _ = write!(display, "\\x{byte:02X}");
+        }
+    }
+    Cow::Owned(display)
+}
+
 /// A path interner that stores the paths and provides path ids.
 #[derive(Debug)]
 pub(crate) struct PathInterner {
@@ -75,3 +164,48 @@ impl PathInterner {
         paths.get_index(id.0.get()).cloned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path with the bytes `0xFF` and `0xC3` that are not valid UTF-8.
+    #[cfg(unix)]
+    fn invalid_path() -> Arc<Path> {
+        use std::os::unix::ffi::OsStrExt;
+        Path::new(std::ffi::OsStr::from_bytes(b"/tmp/a\xFFb/\xC3.txt")).into()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn display_invalid_bytes() {
+        assert_eq!(display_path(&invalid_path()), "/tmp/a\\xFFb/\\xC3.txt");
+        assert!(matches!(
+            display_path(Path::new("/tmp/é")),
+            Cow::Borrowed("/tmp/é")
+        ));
+    }
+
+    #[cfg(all(unix, feature = "serde"))]
+    #[test]
+    fn serialize_invalid_path() {
+        let path = ImmutPath(invalid_path());
+        let json = serde_json::to_value(&path).unwrap();
+        let bytes = b"/tmp/a\xFFb/\xC3.txt".to_vec();
+        let expected = serde_json::json!({ "lossy": "/tmp/a\\xFFb/\\xC3.txt", "bytes": bytes });
+        assert_eq!(json, expected);
+
+        let back: ImmutPath = serde_json::from_value(json).unwrap();
+        assert_eq!(back.0, path.0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_valid_path() {
+        let path = ImmutPath(Path::new("/tmp/é.txt").into());
+        let json = serde_json::to_string(&path).unwrap();
+        assert_eq!(json, "\"/tmp/é.txt\"");
+        let back: ImmutPath = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.0, path.0);
+    }
+}
@@ -0,0 +1,337 @@
+//! Reads a serialized event stream as if the scan ran locally, e.g. the
+//! output of `shr --format json` or `shr --format stream` piped from another
+//! machine.
+//!
+//! An event log records the compact event stream along with the time of each
+//! record, see [`LogRecord`], so that a scan can be replayed with the original
+//! timing by [`Replay`] without touching the filesystem.
+
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Event, EventDisplay, ImmutPath, ShrRx, ShrTx, StreamDecoder, StreamEncoder, StreamRecord,
+};
+
+/// The maximum length of a binary record, which guards against a corrupted
+/// length.
+const MAX_RECORD: u32 = 64 << 20;
+
+/// Reads the events in JSON lines, and sends them as they arrive. The
+/// `largest` files and leaf directories are tracked as
+/// [`crate::Shr::with_largest`] does.
+///
+/// The format is detected from the first line: the compact event stream
+/// begins with a [`StreamRecord::Header`], otherwise each line is an event
+/// with the full paths. The first line is read before returning, and the rest
+/// are read on a background thread, so that the receiver observes the scan
+/// progressively while the stream is still arriving.
+pub fn read_events(
+    mut reader: impl BufRead + Send + 'static,
+    largest: Option<usize>,
+) -> anyhow::Result<ShrRx> {
+    let (tx, rx) = ShrTx::channel(largest);
+
+    let mut line = String::new();
+    let first = loop {
+        line.clear();
+        if reader
+            .read_line(&mut line)
+            .context("failed to read events")?
+            == 0
+        {
+            bail!("the event stream is empty");
+        }
+        if !line.trim().is_empty() {
+            break line.trim();
+        }
+    };
+    let mut decoder = match serde_json::from_str(first) {
+        Ok(header @ StreamRecord::Header { .. }) => {
+            let mut decoder = StreamDecoder::new(tx);
+            decoder.decode(header)?;
+            Decoder::Stream(decoder)
+        }
+        _ => {
+            let mut decoder = Decoder::Full(tx);
+            decoder.decode(first)?;
+            decoder
+        }
+    };
+
+    std::thread::spawn(move || {
+        let result = (|| loop {
+            line.clear();
+            if reader
+                .read_line(&mut line)
+                .context("failed to read events")?
+                == 0
+            {
+                return Ok(());
+            }
+            let line = line.trim();
+            if !line.is_empty() && !decoder.decode(line)? {
+                return Ok(());
+            }
+        })();
+        let result: anyhow::Result<()> = result;
+        if let Err(e) = result {
+            eprintln!("failed to read events: {e:#}");
+        }
+    });
+
+    Ok(rx)
+}
+
+/// Decodes the lines of either format.
+enum Decoder {
+    /// The compact event stream.
+    Stream(StreamDecoder),
+    /// The events with the full paths, which are interned as they appear.
+    Full(ShrTx),
+}
+
+impl Decoder {
+    /// Decodes a line, and sends the event if it is one. It returns
+    /// `Ok(false)` if the receiver is dropped.
+    fn decode(&mut self, line: &str) -> anyhow::Result<bool> {
+        let tx = match self {
+            Self::Stream(decoder) => {
+                let record = serde_json::from_str(line).context("failed to parse record")?;
+                return decoder.decode(record);
+            }
+            Self::Full(tx) => tx,
+        };
+
+        let event: EventDisplay = serde_json::from_str(line).context("failed to parse event")?;
+        let intern = |path: Option<ImmutPath>| match path {
+            Some(path) => Ok(tx.intern(&path.0)),
+            None => bail!("the event has no path: {line}"),
+        };
+        let parent = |parent: Option<ImmutPath>| parent.map(|parent| tx.intern(&parent.0));
+        let event = match event {
+            EventDisplay::Dir { path, parent: p } => Event::Dir {
+                path: intern(path)?,
+                parent: parent(p),
+            },
+            EventDisplay::FileFinish {
+                path,
+                parent: p,
+                size,
+                meta,
+            } => Event::FileFinish {
+                path: intern(path)?,
+                parent: parent(p),
+                size,
+                meta,
+            },
+            EventDisplay::DirFinish {
+                path,
+                size,
+                num_files,
+                meta,
+            } => Event::DirFinish {
+                path: intern(path)?,
+                size,
+                num_files,
+                meta,
+            },
+            EventDisplay::Total {
+                size,
+                num_files,
+                meta,
+            } => Event::Total {
+                size,
+                num_files,
+                meta,
+            },
+        };
+        Ok(tx.send(event))
+    }
+}
+
+/// A record of an event log, which is a record of the compact event stream
+/// stamped with the time since the scan began, e.g.
+/// `{"t":12,"type":"dir","path":1,"parent":null}`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// The milliseconds since the scan began, if recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub t: Option<u64>,
+    /// The record of the compact event stream.
+    #[serde(flatten)]
+    pub record: StreamRecord,
+}
+
+/// The encoding of an event log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// JSON lines of the [`LogRecord`]s, which is also a compact event stream
+    /// readable by [`read_events`].
+    Json,
+    /// The [`LogRecord`]s in frames, each of which is a big-endian `u32`
+    /// length followed by the record in JSON, as the frames of
+    /// `shr agent`.
+    Binary,
+}
+
+/// Records the events of a scan as an event log, until the scan finishes.
+pub async fn record_events(
+    rx: &mut ShrRx,
+    w: &mut impl Write,
+    format: LogFormat,
+) -> anyhow::Result<()> {
+    let begin = Instant::now();
+    let mut encoder = StreamEncoder::default();
+    let mut records = vec![StreamRecord::header()];
+    loop {
+        let t = Some(begin.elapsed().as_millis() as u64);
+        for record in records.drain(..) {
+            write_record(w, format, &LogRecord { t, record })?;
+        }
+        let Some(event) = rx.recv().await else {
+            break;
+        };
+        let event = event.to_raw();
+        encoder.encode(event, |id| rx.get_path(id), &mut records);
+    }
+    w.flush().context("failed to flush event log")?;
+    Ok(())
+}
+
+/// Writes a record of an event log.
+fn write_record(w: &mut impl Write, format: LogFormat, record: &LogRecord) -> anyhow::Result<()> {
+    let payload = serde_json::to_vec(record).context("failed to serialize record")?;
+    match format {
+        LogFormat::Json => {
+            w.write_all(&payload)?;
+            w.write_all(b"\n")?;
+        }
+        LogFormat::Binary => {
+            let len = u32::try_from(payload.len()).context("the record is too large")?;
+            w.write_all(&len.to_be_bytes())?;
+            w.write_all(&payload)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a record of an event log, or `None` at the end of the log.
+fn read_record(r: &mut impl BufRead, format: LogFormat) -> anyhow::Result<Option<LogRecord>> {
+    let payload = match format {
+        LogFormat::Json => {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if r.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if !line.trim().is_empty() {
+                    break line.into_bytes();
+                }
+            }
+        }
+        LogFormat::Binary => {
+            let mut len = [0; 4];
+            match r.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+            let len = u32::from_be_bytes(len);
+            if len > MAX_RECORD {
+                bail!("the record is too large: {len} bytes");
+            }
+            let mut payload = vec![0; len as usize];
+            r.read_exact(&mut payload)?;
+            payload
+        }
+    };
+    let record = serde_json::from_slice(&payload).context("failed to parse record")?;
+    Ok(Some(record))
+}
+
+/// Replays an event log as if the scan ran locally.
+///
+/// The format of the log is detected from the first byte, since a JSON
+/// record begins with `{` and a binary one with the high byte of its length.
+#[derive(Debug)]
+pub struct Replay<R> {
+    reader: R,
+    largest: Option<usize>,
+    timing: bool,
+}
+
+impl<R: BufRead + Send + 'static> Replay<R> {
+    /// Creates a replay reading the log from the reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            largest: None,
+            timing: false,
+        }
+    }
+
+    /// Tracks the `largest` files and leaf directories, as
+    /// [`crate::Shr::with_largest`] does.
+    pub fn with_largest(mut self, largest: Option<usize>) -> Self {
+        self.largest = largest;
+        self
+    }
+
+    /// Delays each record until its recorded time since the replay began,
+    /// otherwise the records are sent as fast as they are read.
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Runs the replay. The header is checked before returning, and the rest
+    /// of the log is sent on a background thread.
+    pub fn run(self) -> anyhow::Result<ShrRx> {
+        let Self {
+            mut reader,
+            largest,
+            timing,
+        } = self;
+        let format = match reader.fill_buf().context("failed to read event log")? {
+            [b'{' | b' ' | b'\t' | b'\r' | b'\n', ..] => LogFormat::Json,
+            [_, ..] => LogFormat::Binary,
+            [] => bail!("the event log is empty"),
+        };
+
+        let (tx, rx) = ShrTx::channel(largest);
+        let mut decoder = StreamDecoder::new(tx);
+        match read_record(&mut reader, format)? {
+            Some(LogRecord { record, .. }) => {
+                decoder.decode(record)?;
+            }
+            None => bail!("the event log is empty"),
+        }
+
+        let begin = Instant::now();
+        std::thread::spawn(move || {
+            let result = (|| loop {
+                let Some(LogRecord { t, record }) = read_record(&mut reader, format)? else {
+                    return Ok(());
+                };
+                if let Some(t) = t.filter(|_| timing) {
+                    let due = begin + Duration::from_millis(t);
+                    std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                }
+                if !decoder.decode(record)? {
+                    return Ok(());
+                }
+            })();
+            let result: anyhow::Result<()> = result;
+            if let Err(e) = result {
+                eprintln!("failed to replay events: {e:#}");
+            }
+        });
+
+        Ok(rx)
+    }
+}
@@ -8,30 +8,93 @@
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use crate::{Event, EventRef, PathId, PathInterner};
+use budget::FdBudget;
+use throttle::RateLimiter;
 
+use crate::age::{AgeClock, unix_secs};
+use crate::{
+    AgeUsage, DirMeta, Event, EventRef, FileMeta, Glob, Largest, Owner, OwnerUsage, PathId,
+    PathInterner, Timestamp,
+};
+
+mod budget;
+#[cfg(all(feature = "tokio", target_os = "linux"))]
+mod linux;
+#[cfg(feature = "tokio")]
+mod pending;
+mod throttle;
 #[cfg(feature = "tokio")]
 mod tokio_backend;
 #[cfg(feature = "tokio")]
 pub use tokio_backend::*;
 
+/// The strategy to walk the directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Walker {
+    /// Stats every entry by its full path, which is portable.
+    Path,
+    /// Opens every directory once, reads the entries by `getdents64`, and
+    /// stats them relative to the directory by `fstatat`. The entry types are
+    /// used to skip the stat calls for directories. It is only supported on
+    /// Linux, and falls back to [`Walker::Path`] elsewhere.
+    #[default]
+    Fd,
+}
+
 /// The main struct to scan the directory recursively.
 pub struct Shr {
-    /// The path to scan.
-    path: PathBuf,
+    /// The paths to scan.
+    paths: Vec<PathBuf>,
     /// The path interner.
     path_interner: Arc<PathInterner>,
     /// The maximum depth to report.
     max_depth: usize,
+    /// Whether to aggregate the usage by owners.
+    owners: bool,
+    /// The timestamp to aggregate the usage by ages.
+    ages: Option<Timestamp>,
+    /// Whether to compute the fingerprints of directories.
+    fingerprints: bool,
+    /// The number of the largest files and leaf directories to keep.
+    largest: Option<usize>,
+    /// The number of threads to scan, or zero to use all the cores.
+    threads: usize,
+    /// The maximum number of file descriptors opened at the same time.
+    open_files: usize,
+    /// The maximum number of metadata operations per second.
+    max_iops: Option<u32>,
+    /// Whether to scan in idle I/O priority and low CPU priority.
+    gentle: bool,
+    /// The strategy to walk the directories.
+    walker: Walker,
+    /// The entries to skip.
+    excludes: Vec<Glob>,
 }
 
 impl Shr {
     /// Creates a `Shr` that scans files in the `path`.
     pub fn new(path: PathBuf) -> Self {
+        Self::from_roots(vec![path])
+    }
+
+    /// Creates a `Shr` that scans files in several roots concurrently, sharing
+    /// a single event stream. The roots nested inside another root are
+    /// skipped. A [`Event::Total`] is sent after all the roots are finished.
+    pub fn from_roots(paths: Vec<PathBuf>) -> Self {
         Self {
-            path,
+            paths,
             path_interner: Arc::new(PathInterner::default()),
             max_depth: usize::MAX,
+            owners: false,
+            ages: None,
+            fingerprints: false,
+            largest: None,
+            threads: 0,
+            open_files: 256,
+            max_iops: None,
+            gentle: false,
+            walker: Walker::default(),
+            excludes: Vec::new(),
         }
     }
 
@@ -43,28 +106,401 @@ impl Shr {
         self
     }
 
+    /// Aggregates bytes and file counts per uid and gid for every directory.
+    /// The owners are reported in [`FileMeta`] and [`DirMeta`]. It is only
+    /// available on unix.
+    pub fn with_owners(mut self, owners: bool) -> Self {
+        self.owners = owners;
+        self
+    }
+
+    /// Aggregates bytes into age buckets by the `timestamp` for every
+    /// directory. The ages are reported in [`FileMeta`] and [`DirMeta`].
+    pub fn with_ages(mut self, timestamp: Option<Timestamp>) -> Self {
+        self.ages = timestamp;
+        self
+    }
+
+    /// Computes a Merkle-style fingerprint for every directory from the names,
+    /// sizes and content hashes of its children, so that identical subtrees
+    /// have the same fingerprint. It reads the content of all the files. The
+    /// fingerprints are reported in [`FileMeta`] and [`DirMeta`].
+    #[cfg(feature = "hash")]
+    pub fn with_fingerprints(mut self, fingerprints: bool) -> Self {
+        self.fingerprints = fingerprints;
+        self
+    }
+
+    /// Keeps the `k` largest files and leaf directories during the scan, which
+    /// are available from [`ShrRx::largest`] even before the scan finishes.
+    pub fn with_largest(mut self, k: Option<usize>) -> Self {
+        self.largest = k;
+        self
+    }
+
+    /// Sets the number of threads to scan, or zero to use all the cores. The
+    /// scan runs in a dedicated thread pool, which is not shared with the host
+    /// application. If the pool cannot be built, e.g. the threads cannot be
+    /// spawned, the error is printed and the global pool of rayon is used.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Sets the maximum number of file descriptors opened by the scan at the
+    /// same time, which defaults to 256. Each directory is listed and closed
+    /// before scanning its children, so the budget holds however deep the
+    /// tree is. The [`Walker::Fd`] keeps the directories open to reach their
+    /// entries relative to them while the budget allows, except one per
+    /// thread reserved for listing, and falls back to the full paths
+    /// otherwise. Only the directories whose entries may exceed `PATH_MAX`
+    /// are kept open beyond the budget.
+    pub fn with_open_files(mut self, open_files: usize) -> Self {
+        self.open_files = open_files;
+        self
+    }
+
+    /// Rate-limits the metadata operations (stat, listing directories and
+    /// hashing files) to `max_iops` per second.
+    pub fn with_max_iops(mut self, max_iops: Option<u32>) -> Self {
+        self.max_iops = max_iops;
+        self
+    }
+
+    /// Scans in a low-impact mode, which sets the scanning threads to idle
+    /// I/O priority (`ioprio_set`) and the lowest CPU niceness. It is only
+    /// supported on Linux.
+    pub fn with_gentle(mut self, gentle: bool) -> Self {
+        self.gentle = gentle;
+        self
+    }
+
+    /// Sets the strategy to walk the directories, which defaults to
+    /// [`Walker::Fd`].
+    pub fn with_walker(mut self, walker: Walker) -> Self {
+        self.walker = walker;
+        self
+    }
+
+    /// Skips the entries matching any of the globs, which are neither reported
+    /// nor counted. The relative globs match at any depth, e.g. `target/debug`
+    /// skips every `target/debug` directory. The roots are never skipped.
+    pub fn with_excludes(mut self, excludes: Vec<Glob>) -> Self {
+        self.excludes = excludes.into_iter().map(Glob::anywhere).collect();
+        self
+    }
+
     /// Runs the scan routine.
     pub async fn run(self) -> ShrRx {
         let (tx, rx) = mpsc::unbounded_channel();
         let path_interner = self.path_interner;
 
-        let path = self.path.into();
-        let task = ShrTask {
-            path_id: path_interner.intern(&path),
-            parent: None,
-            path,
-            remain_report_depth: self.max_depth,
-        };
+        let tasks = dedup_roots(self.paths)
+            .into_iter()
+            .map(|path| {
+                let path = path.into();
+                ShrTask {
+                    path_id: path_interner.intern(&path),
+                    parent: None,
+                    path,
+                    remain_report_depth: self.max_depth,
+                }
+            })
+            .collect::<Vec<_>>();
         let path_mgr2 = path_interner.clone();
+        let largest = self.largest.map(|k| Arc::new(Largest::new(k)));
+        let largest2 = largest.clone();
+        let gentle = self.gentle;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .thread_name(|i| format!("shr-scan-{i}"))
+            .start_handler(move |_| {
+                if gentle {
+                    throttle::lower_thread_priority();
+                }
+            })
+            .build()
+            .inspect_err(|e| eprintln!("failed to build the scan thread pool: {e}"))
+            .ok();
+        // Leaves a file descriptor for each thread to list a directory.
+        let reserved = pool
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |pool| {
+                pool.current_num_threads()
+            });
         tokio::spawn(tokio::task::spawn_blocking(move || {
             let shared = Shared {
                 path_mgr: &path_mgr2,
                 tx,
                 follow_links: true,
+                owners: self.owners,
+                ages: self.ages.map(AgeClock::new),
+                fingerprints: self.fingerprints,
+                largest: largest2,
+                fds: FdBudget::new(self.open_files, reserved),
+                limiter: self.max_iops.map(RateLimiter::new),
+                walker: self.walker,
+                excludes: self.excludes,
             };
-            task.exec(&shared)
+            let total = pending::PendingDir::total();
+            let scan = || {
+                rayon::scope(|scope| {
+                    for task in tasks {
+                        let (total, shared) = (total.clone(), &shared);
+                        scope.spawn(move |scope| {
+                            if let Some(summary) = task.exec(scope, shared, &total) {
+                                total.merge(summary);
+                            }
+                        });
+                    }
+                });
+            };
+            // Falls back to the global pool if the dedicated one fails.
+            match pool {
+                Some(pool) => pool.install(scan),
+                None => scan(),
+            }
+            let total = total.take_total();
+            let event = Event::Total {
+                size: total.size,
+                num_files: total.num_files,
+                meta: total.dir_meta(),
+            };
+            let _ = shared.tx.send(event);
         }));
 
-        ShrRx { path_interner, rx }
+        ShrRx {
+            path_interner,
+            rx,
+            largest,
+        }
+    }
+}
+
+/// Removes the roots that are the same as or nested inside another root, since
+/// they are already covered by the scan.
+fn dedup_roots(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let canonical = paths
+        .iter()
+        .map(|path| std::fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+        .collect::<Vec<_>>();
+
+    let covered_by = |i: usize| {
+        canonical.iter().enumerate().find(|(j, other)| {
+            let nested = canonical[i] != **other && canonical[i].starts_with(other);
+            let same_before = canonical[i] == **other && *j < i;
+            nested || same_before
+        })
+    };
+
+    paths
+        .iter()
+        .enumerate()
+        .filter_map(|(i, path)| {
+            if let Some((j, _)) = covered_by(i) {
+                eprintln!(
+                    "skip {}: it is covered by {}",
+                    path.display(),
+                    paths[j].display()
+                );
+                return None;
+            }
+            Some(path.clone())
+        })
+        .collect()
+}
+
+/// The aggregated result of a scanned entry.
+#[derive(Default)]
+pub(crate) struct Summary {
+    /// The number of files.
+    pub num_files: usize,
+    /// The size in bytes.
+    pub size: u64,
+    /// The number of directories, including itself.
+    pub num_dirs: usize,
+    /// The usage grouped by owners.
+    pub owners: Option<OwnerUsage>,
+    /// The usage grouped by ages.
+    pub ages: Option<AgeUsage>,
+    /// The state to compute the fingerprint.
+    #[cfg(feature = "hash")]
+    pub fingerprint: crate::hash::FingerprintState,
+}
+
+impl Summary {
+    /// Creates the summary of a single file.
+    pub(crate) fn file(size: u64, meta: Option<&FileMeta>, clock: Option<&AgeClock>) -> Self {
+        let owners = meta.and_then(|meta| meta.owner).map(|owner| {
+            let mut owners = OwnerUsage::default();
+            owners.add_file(owner, size);
+            owners
+        });
+        let ages = meta.zip(clock).and_then(|(meta, clock)| {
+            let bucket = meta.age?;
+            let mut ages = AgeUsage::default();
+            ages.add_file(bucket, clock.by.of(meta), size);
+            Some(ages)
+        });
+        Self {
+            num_files: 1,
+            size,
+            num_dirs: 0,
+            owners,
+            ages,
+            #[cfg(feature = "hash")]
+            fingerprint: crate::hash::FingerprintState::file(meta.and_then(|meta| meta.hash)),
+        }
+    }
+
+    /// Creates the summary of an entry that cannot be stated or a directory
+    /// that cannot be listed, whose fingerprint is unknown.
+    fn unreadable() -> Self {
+        #[allow(unused_mut)]
+        let mut summary = Self::default();
+        #[cfg(feature = "hash")]
+        summary.fingerprint.invalidate();
+        summary
+    }
+
+    /// Merges two summaries.
+    fn merge(mut self, other: Self) -> Self {
+        self.num_files += other.num_files;
+        self.size += other.size;
+        self.num_dirs += other.num_dirs;
+        self.owners = match (self.owners, other.owners) {
+            (Some(mut a), Some(b)) => {
+                a.merge(&b);
+                Some(a)
+            }
+            (a, b) => a.or(b),
+        };
+        self.ages = match (self.ages, other.ages) {
+            (Some(mut a), Some(b)) => {
+                a.merge(&b);
+                Some(a)
+            }
+            (a, b) => a.or(b),
+        };
+        #[cfg(feature = "hash")]
+        self.fingerprint.merge(other.fingerprint);
+        self
+    }
+
+    /// Prepares the summary of an entry to be merged into its parent.
+    fn into_child(self, _name: &std::ffi::OsStr, _shared: &Shared) -> Self {
+        #[cfg(feature = "hash")]
+        if _shared.fingerprints {
+            let mut child = self;
+            child.fingerprint.adopt(_name, child.size);
+            return child;
+        }
+        self
+    }
+
+    /// Finishes the summary of a directory after all children are merged.
+    fn into_dir(mut self, path: PathId, shared: &Shared) -> Self {
+        if let Some(largest) = &shared.largest {
+            if self.num_dirs == 0 {
+                largest.add_dir(path, self.size);
+            }
+        }
+        self.num_dirs += 1;
+        #[cfg(feature = "hash")]
+        if shared.fingerprints {
+            self.fingerprint.finish();
+        }
+        self
+    }
+
+    /// Collects the extra statistics to report.
+    fn dir_meta(&self) -> Option<Box<DirMeta>> {
+        let meta = DirMeta {
+            owners: self.owners.clone(),
+            ages: self.ages,
+            #[cfg(feature = "hash")]
+            fingerprint: self.fingerprint.own,
+        };
+        #[cfg(feature = "hash")]
+        let requested = meta.fingerprint.is_some();
+        #[cfg(not(feature = "hash"))]
+        let requested = false;
+        let requested = requested || meta.owners.is_some() || meta.ages.is_some();
+        requested.then(|| Box::new(meta))
+    }
+}
+
+/// The metadata of a file used by the scan.
+pub(crate) struct FileStat {
+    /// The size in bytes.
+    pub size: u64,
+    /// The owner of the file.
+    pub owner: Option<Owner>,
+    /// The last modification time in seconds since the unix epoch.
+    pub modified: Option<u64>,
+    /// The last access time in seconds since the unix epoch.
+    pub accessed: Option<u64>,
+}
+
+impl FileStat {
+    /// Gets the stat from the metadata.
+    pub fn from_metadata(mt: &std::fs::Metadata) -> Self {
+        Self {
+            size: mt.len(),
+            owner: Owner::from_metadata(mt),
+            modified: mt.modified().ok().and_then(unix_secs),
+            accessed: mt.accessed().ok().and_then(unix_secs),
+        }
+    }
+}
+
+impl Shared<'_> {
+    /// Whether the entry is skipped by the excludes.
+    fn is_excluded(&self, path: &Path) -> bool {
+        // The relative globs begin with `**`, so they are matched against the
+        // path without its root, e.g. `/`.
+        let root = path.ancestors().last().unwrap_or(path);
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        self.excludes
+            .iter()
+            .any(|glob| match glob.as_str().starts_with('/') {
+                true => glob.matches(path),
+                false => glob.matches(relative),
+            })
+    }
+
+    /// Waits for a metadata operation to be allowed.
+    fn throttle(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.wait();
+        }
+    }
+
+    /// Collects the extra metadata to report for a file.
+    fn file_meta(&self, path: &Path, stat: &FileStat) -> Option<Box<FileMeta>> {
+        if !self.owners && self.ages.is_none() && !self.fingerprints {
+            return None;
+        }
+
+        let mut meta = FileMeta {
+            owner: self.owners.then_some(stat.owner).flatten(),
+            ..FileMeta::default()
+        };
+        if let Some(clock) = &self.ages {
+            meta.modified = stat.modified;
+            meta.accessed = stat.accessed;
+            meta.age = clock.bucket(&meta);
+        }
+        #[cfg(feature = "hash")]
+        if self.fingerprints {
+            self.throttle();
+            let _permit = self.fds.acquire();
+            meta.hash = crate::ContentHash::of_file(path)
+                .inspect_err(|e| eprintln!("failed to hash {}: {e}", path.display()))
+                .ok();
+        }
+        #[cfg(not(feature = "hash"))]
+        let _ = path;
+        Some(Box::new(meta))
     }
 }
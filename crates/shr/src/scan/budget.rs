@@ -0,0 +1,60 @@
+//! The budget of open file descriptors.
+
+use std::sync::{Condvar, Mutex};
+
+/// A counting semaphore limiting the number of file descriptors opened by the
+/// scan at the same time.
+#[derive(Debug)]
+pub(crate) struct FdBudget {
+    available: Mutex<usize>,
+    released: Condvar,
+    /// The number of permits never taken by [`FdBudget::try_hold`].
+    reserved: usize,
+}
+
+impl FdBudget {
+    /// Creates a budget of `n` file descriptors, which is at least one. The
+    /// `reserved` ones are left for [`FdBudget::acquire`], e.g. one per
+    /// thread, so that the file descriptors held for long never starve the
+    /// short-lived ones.
+    pub fn new(n: usize, reserved: usize) -> Self {
+        Self {
+            available: Mutex::new(n.max(1)),
+            released: Condvar::new(),
+            reserved,
+        }
+    }
+
+    /// Blocks until a file descriptor can be opened. The permit must not be
+    /// held while acquiring another one, otherwise the scan may deadlock.
+    pub fn acquire(&self) -> FdPermit<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+        FdPermit(self)
+    }
+
+    /// Takes a permit to keep a file descriptor open for long, unless it
+    /// would leave fewer than the reserved ones. It never blocks, so the
+    /// permits can be held while others are acquired.
+    pub fn try_hold(&self) -> Option<FdPermit<'_>> {
+        let mut available = self.available.lock().unwrap();
+        if *available <= self.reserved {
+            return None;
+        }
+        *available -= 1;
+        Some(FdPermit(self))
+    }
+}
+
+/// A permit to open a file descriptor, released on drop.
+pub(crate) struct FdPermit<'a>(&'a FdBudget);
+
+impl Drop for FdPermit<'_> {
+    fn drop(&mut self) {
+        *self.0.available.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
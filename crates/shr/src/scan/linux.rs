@@ -0,0 +1,289 @@
+//! The fd-relative walker on Linux.
+//!
+//! Each directory is opened once, its entries are read by `getdents64`, and
+//! the files are stated by `fstatat` relative to the directory, so that the
+//! kernel does not resolve the long path prefixes again and again. The entry
+//! types reported by `getdents64` are used to skip the stat calls for the
+//! directories and special files.
+//!
+//! A directory is kept open after listing it while the [`super::FdBudget`]
+//! allows, so that its subdirectories are opened by `openat` relative to it,
+//! and it is closed once they are opened and its entries are stated. When the
+//! budget is exhausted, the directory is closed right after listing it, and
+//! its entries are reached by their full paths instead, unless they may
+//! exceed `PATH_MAX`.
+
+use std::ffi::{CStr, CString, OsStr};
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use rayon::Scope;
+
+use super::budget::FdPermit;
+use super::pending::PendingDir;
+use super::tokio_backend::{CHUNK_SIZE, Report, Shared, ShrTask};
+use super::{FileStat, Summary};
+use crate::Owner;
+
+/// The size of the buffer to read the directory entries.
+const DIRENT_BUF_SIZE: usize = 32 * 1024;
+/// The maximum length of a file name in bytes on Linux.
+const NAME_MAX: usize = 255;
+
+/// An open directory, which holds a permit of the budget while it is kept
+/// open for its entries.
+pub(crate) struct DirFd<'a> {
+    fd: OwnedFd,
+    _permit: Option<FdPermit<'a>>,
+}
+
+/// An entry read by `getdents64`.
+struct RawEntry {
+    /// The file name.
+    name: CString,
+    /// The file type, one of the `DT_*` constants.
+    d_type: u8,
+}
+
+impl<'a> DirFd<'a> {
+    /// Opens the directory at `path`, or by its file name relative to the
+    /// `parent`.
+    fn open(parent: Option<&DirFd>, path: &Path) -> io::Result<Self> {
+        let (dirfd, path) = match (parent, path.file_name()) {
+            (Some(parent), Some(name)) => (parent.fd.as_raw_fd(), name),
+            _ => (libc::AT_FDCWD, path.as_os_str()),
+        };
+        let path = CString::new(path.as_bytes())?;
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        // SAFETY: `path` is a valid C string, and `dirfd` is either an open
+        // directory or `AT_FDCWD`.
+        let fd = unsafe { libc::openat(dirfd, path.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` is just opened and owned by nobody else.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        Ok(Self { fd, _permit: None })
+    }
+
+    /// Holds the permit while the directory is open.
+    fn with_permit(self, permit: FdPermit<'a>) -> Self {
+        Self {
+            fd: self.fd,
+            _permit: Some(permit),
+        }
+    }
+
+    /// Reads all the entries except `.` and `..`.
+    fn entries(&self) -> io::Result<Vec<RawEntry>> {
+        let mut buf = vec![0u8; DIRENT_BUF_SIZE];
+        let mut entries = Vec::new();
+        loop {
+            // SAFETY: the buffer is valid for writes of `DIRENT_BUF_SIZE`
+            // bytes, and the kernel writes at most that many bytes.
+            let n = unsafe {
+                libc::syscall(
+                    libc::SYS_getdents64,
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr(),
+                    DIRENT_BUF_SIZE,
+                )
+            };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if n == 0 {
+                return Ok(entries);
+            }
+
+            // Parses the `linux_dirent64` records: `d_ino: u64`, `d_off: i64`,
+            // `d_reclen: u16`, `d_type: u8`, then the nul-terminated name.
+            let mut records = &buf[..n as usize];
+            while records.len() > 19 {
+                let reclen = u16::from_ne_bytes([records[16], records[17]]) as usize;
+                let (record, rest) = records.split_at(reclen.min(records.len()));
+                records = rest;
+
+                let Ok(name) = CStr::from_bytes_until_nul(&record[19..]) else {
+                    continue;
+                };
+                if matches!(name.to_bytes(), b"." | b"..") {
+                    continue;
+                }
+                entries.push(RawEntry {
+                    name: name.to_owned(),
+                    d_type: record[18],
+                });
+            }
+        }
+    }
+
+    /// Stats the entry relative to the directory.
+    fn stat(&self, name: &CStr, follow: bool) -> io::Result<libc::stat64> {
+        let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+        let mut st = MaybeUninit::<libc::stat64>::uninit();
+        // SAFETY: `name` is a valid C string, and `st` is valid for writes.
+        let res =
+            unsafe { libc::fstatat64(self.fd.as_raw_fd(), name.as_ptr(), st.as_mut_ptr(), flags) };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fstatat64` succeeded, so `st` is initialized.
+        Ok(unsafe { st.assume_init() })
+    }
+}
+
+/// Gets the stat of a file.
+fn file_stat(st: &libc::stat64) -> FileStat {
+    FileStat {
+        size: st.st_size as u64,
+        owner: Some(Owner {
+            uid: st.st_uid,
+            gid: st.st_gid,
+        }),
+        modified: u64::try_from(st.st_mtime).ok(),
+        accessed: u64::try_from(st.st_atime).ok(),
+    }
+}
+
+/// Lists the directory, relative to the `parent` if it is given, and scans
+/// the entries in chunks. The subdirectories are scheduled in the `scope`.
+pub(super) fn scan_dir_at<'s>(
+    task: ShrTask,
+    parent: Option<Arc<DirFd<'s>>>,
+    scope: &Scope<'s>,
+    shared: &'s Shared<'s>,
+    dir: Arc<PendingDir>,
+) {
+    let listing = {
+        shared.throttle();
+        let _permit = shared.fds.acquire();
+        DirFd::open(parent.as_deref(), &task.path)
+            .and_then(|fd| Ok((fd.entries()?, fd)))
+            .report()
+    };
+    drop(parent);
+    let Some((entries, fd)) = listing else {
+        dir.merge(Summary::unreadable());
+        dir.release(shared);
+        return;
+    };
+
+    // Keeps the directory open for its entries if the budget allows, or if
+    // they may exceed `PATH_MAX` and cannot be reached by their full paths.
+    let fd = match shared.fds.try_hold() {
+        Some(permit) => Some(Arc::new(fd.with_permit(permit))),
+        None if may_exceed_path_max(&task.path) => Some(Arc::new(fd)),
+        None => None,
+    };
+
+    let mut entries = entries.into_iter();
+    let mut chunk = entries.by_ref().take(CHUNK_SIZE).collect::<Vec<_>>();
+    loop {
+        let next = entries.by_ref().take(CHUNK_SIZE).collect::<Vec<_>>();
+        if next.is_empty() {
+            // Scans the last chunk in place.
+            scan_entries(&task, chunk, fd, scope, shared, &dir);
+            break;
+        }
+        dir.hold(1);
+        let (task, fd, dir) = (task.clone_dir(), fd.clone(), dir.clone());
+        scope.spawn(move |scope| {
+            scan_entries(&task, chunk, fd, scope, shared, &dir);
+            dir.release(shared);
+        });
+        chunk = next;
+    }
+    dir.release(shared);
+}
+
+/// Scans a chunk of entries in the directory, relative to its `fd` if it is
+/// kept open.
+fn scan_entries<'s>(
+    task: &ShrTask,
+    entries: Vec<RawEntry>,
+    fd: Option<Arc<DirFd<'s>>>,
+    scope: &Scope<'s>,
+    shared: &'s Shared<'s>,
+    dir: &Arc<PendingDir>,
+) {
+    let mut summary = Summary::default();
+    for entry in entries {
+        let name = OsStr::from_bytes(entry.name.to_bytes());
+        let path = task.path.join(name);
+        if shared.is_excluded(&path) {
+            continue;
+        }
+        let child = task.child(shared, path.into());
+        if let Some(child) = scan_entry(child, &entry, fd.as_ref(), scope, shared, dir) {
+            summary = summary.merge(child.into_child(name, shared));
+        }
+    }
+    dir.merge(summary);
+}
+
+/// Scans an entry in the directory `fd`, or by its full path if the directory
+/// is closed. It returns the summary of a file, or `None` if it is a
+/// directory, which is scheduled in the `scope`.
+fn scan_entry<'s>(
+    task: ShrTask,
+    entry: &RawEntry,
+    fd: Option<&Arc<DirFd<'s>>>,
+    scope: &Scope<'s>,
+    shared: &'s Shared<'s>,
+    dir: &Arc<PendingDir>,
+) -> Option<Summary> {
+    let is_dir = match entry.d_type {
+        libc::DT_DIR => true,
+        libc::DT_REG | libc::DT_LNK | libc::DT_UNKNOWN => {
+            // Follows the links as `std::fs::metadata` does.
+            let follow = entry.d_type != libc::DT_REG;
+            shared.throttle();
+            let st = match fd {
+                Some(fd) => fd.stat(&entry.name, follow),
+                None => stat_path(&task.path, follow),
+            };
+            let Some(st) = st.report() else {
+                return Some(Summary::unreadable());
+            };
+            match st.st_mode & libc::S_IFMT {
+                libc::S_IFREG => return Some(task.finish_file(shared, &file_stat(&st))),
+                libc::S_IFDIR => true,
+                _ => false,
+            }
+        }
+        _ => false,
+    };
+    if !is_dir {
+        return Some(Summary::file(0, None, None));
+    }
+
+    let pending = task.start_dir(shared, dir);
+    let parent = fd.cloned();
+    scope.spawn(move |scope| scan_dir_at(task, parent, scope, shared, pending));
+    None
+}
+
+/// Stats the file at the full path.
+fn stat_path(path: &Path, follow: bool) -> io::Result<libc::stat64> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+    let mut st = MaybeUninit::<libc::stat64>::uninit();
+    // SAFETY: `path` is a valid C string, and `st` is valid for writes.
+    let res = unsafe { libc::fstatat64(libc::AT_FDCWD, path.as_ptr(), st.as_mut_ptr(), flags) };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fstatat64` succeeded, so `st` is initialized.
+    Ok(unsafe { st.assume_init() })
+}
+
+/// Whether the paths of the entries in the directory may exceed `PATH_MAX`,
+/// given the names are at most `NAME_MAX` bytes.
+fn may_exceed_path_max(dir: &Path) -> bool {
+    dir.as_os_str().len() + 1 + NAME_MAX >= libc::PATH_MAX as usize
+}
@@ -0,0 +1,130 @@
+//! The directories whose children are still being scanned.
+
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::{Shared, Summary};
+use crate::{Event, PathId};
+
+/// A directory whose children are still being scanned. It counts the
+/// unfinished parts, and reports to its parent once all of them are finished,
+/// so that the walker never waits for (or recurses into) the children.
+pub(crate) struct PendingDir {
+    /// The directory, or `None` for the grand total of the roots.
+    dir: Option<DirInfo>,
+    /// The number of unfinished parts: the child directories and the chunks of
+    /// entries, plus one held by the listing of the directory itself.
+    pending: AtomicUsize,
+    /// The summary merged so far and the parent, which are taken when the
+    /// directory is finished.
+    state: Mutex<PendingState>,
+}
+
+/// The directory to report when it is finished.
+struct DirInfo {
+    /// The path id of the directory.
+    path_id: PathId,
+    /// The file name of the directory.
+    name: OsString,
+    /// Whether to report the [`Event::DirFinish`].
+    report: bool,
+}
+
+#[derive(Default)]
+struct PendingState {
+    summary: Summary,
+    parent: Option<Arc<PendingDir>>,
+}
+
+impl PendingDir {
+    /// Creates the grand total of the roots, which is never finished.
+    pub fn total() -> Arc<Self> {
+        Arc::new(Self {
+            dir: None,
+            pending: AtomicUsize::new(1),
+            state: Mutex::default(),
+        })
+    }
+
+    /// Creates a child directory, which holds a part of the parent until it is
+    /// finished.
+    pub fn child(parent: &Arc<Self>, path_id: PathId, name: OsString, report: bool) -> Arc<Self> {
+        parent.hold(1);
+        Arc::new(Self {
+            dir: Some(DirInfo {
+                path_id,
+                name,
+                report,
+            }),
+            pending: AtomicUsize::new(1),
+            state: Mutex::new(PendingState {
+                summary: Summary::default(),
+                parent: Some(parent.clone()),
+            }),
+        })
+    }
+
+    /// Adds `n` unfinished parts.
+    pub fn hold(&self, n: usize) {
+        self.pending.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Merges the summary of finished children.
+    pub fn merge(&self, summary: Summary) {
+        let mut state = self.state.lock().unwrap();
+        state.summary = std::mem::take(&mut state.summary).merge(summary);
+    }
+
+    /// Prepares the summary of a child to be merged into this directory. The
+    /// roots are merged into the total as is.
+    pub fn adopt(&self, name: &std::ffi::OsStr, summary: Summary, shared: &Shared) -> Summary {
+        if self.dir.is_some() {
+            summary.into_child(name, shared)
+        } else {
+            summary
+        }
+    }
+
+    /// Takes the summary of the grand total.
+    pub fn take_total(&self) -> Summary {
+        std::mem::take(&mut self.state.lock().unwrap().summary)
+    }
+
+    /// Marks one part as finished. The directories whose parts are all
+    /// finished are reported and merged into their parents, bottom-up.
+    pub fn release(self: Arc<Self>, shared: &Shared) {
+        let mut node = self;
+        loop {
+            if node.pending.fetch_sub(1, Ordering::AcqRel) != 1 {
+                return;
+            }
+            let Some(info) = &node.dir else {
+                return;
+            };
+            // Takes the parent out, so that dropping a deep chain of finished
+            // directories does not recurse.
+            let (summary, parent) = {
+                let mut state = node.state.lock().unwrap();
+                (std::mem::take(&mut state.summary), state.parent.take())
+            };
+
+            let summary = summary.into_dir(info.path_id, shared);
+            if info.report {
+                let event = Event::DirFinish {
+                    path: info.path_id,
+                    size: summary.size,
+                    num_files: summary.num_files,
+                    meta: summary.dir_meta(),
+                };
+                let _ = shared.tx.send(event);
+            }
+
+            let Some(parent) = parent else {
+                return;
+            };
+            parent.merge(parent.adopt(&info.name, summary, shared));
+            node = parent;
+        }
+    }
+}
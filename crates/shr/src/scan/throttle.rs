@@ -0,0 +1,78 @@
+//! Throttles the scan to reduce its impact on busy servers.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Rate-limits the metadata operations by spacing them evenly.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// The time between two operations.
+    interval: Duration,
+    /// The time when the next operation is allowed.
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `iops` operations per second.
+    pub fn new(iops: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / iops.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Blocks until an operation is allowed.
+    pub fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        let now = Instant::now();
+        if slot > now {
+            std::thread::sleep(slot - now);
+        }
+    }
+}
+
+/// Sets the calling thread to idle I/O priority and the lowest CPU priority.
+/// It is only supported on Linux.
+pub(crate) fn lower_thread_priority() {
+    #[cfg(target_os = "linux")]
+    {
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+        // SAFETY: `gettid` has no preconditions.
+        let tid = unsafe { libc::gettid() };
+        // SAFETY: `ioprio_set` only reads the integer arguments, and a `tid`
+        // targets the calling thread only.
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                tid,
+                IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+            )
+        };
+        if res != 0 {
+            eprintln!(
+                "failed to set io priority: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        // SAFETY: `setpriority` only reads the integer arguments. On Linux,
+        // the nice value of a `tid` only applies to the calling thread.
+        let res = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, 19) };
+        if res != 0 {
+            eprintln!(
+                "failed to set cpu priority: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
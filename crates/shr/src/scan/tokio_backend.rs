@@ -1,9 +1,21 @@
-//! rayon is used to avoid "Too many open files" error.
+//! The directories are scanned in a dedicated rayon pool. Each directory is
+//! listed and closed before scanning its children, and the listing is limited
+//! by the [`FdBudget`] to avoid "Too many open files" error.
+//!
+//! The walker does not recurse. The subdirectories and the chunks of entries
+//! are pushed into the work-stealing queue of a [`rayon::scope`], and each
+//! [`PendingDir`] counts its unfinished parts to emit [`Event::DirFinish`]
+//! when all of them are finished. So the stack depth of the workers does not
+//! grow with the depth of the tree.
 
-use rayon::iter::{ParallelBridge, ParallelIterator};
+use std::collections::HashSet;
+
+use rayon::Scope;
 pub(crate) use tokio::sync::mpsc;
 
-use crate::PathInterner;
+use super::pending::PendingDir;
+
+use crate::{Largest, PathInterner};
 
 use super::*;
 /// The receiver for the events.
@@ -11,11 +23,13 @@ use super::*;
 pub struct ShrRx {
     pub(crate) path_interner: Arc<PathInterner>,
     pub(crate) rx: mpsc::UnboundedReceiver<Event>,
+    /// The largest files and leaf directories, if requested.
+    pub(crate) largest: Option<Arc<Largest>>,
 }
 
 impl ShrRx {
     /// Receives an event.
-    pub async fn recv(&mut self) -> Option<EventRef> {
+    pub async fn recv(&mut self) -> Option<EventRef<'_>> {
         self.rx.recv().await.map(|data| EventRef { data, rx: self })
     }
 
@@ -23,6 +37,88 @@ impl ShrRx {
     pub fn get_path(&self, id: PathId) -> Option<Arc<Path>> {
         self.path_interner.as_ref().get(id)
     }
+
+    /// Gets the largest files and leaf directories found so far, if requested
+    /// by [`Shr::with_largest`].
+    pub fn largest(&self) -> Option<&Largest> {
+        self.largest.as_deref()
+    }
+
+    /// Gets a handle to resolve the paths, which can be shared with other
+    /// tasks while receiving the events.
+    pub fn resolver(&self) -> PathResolver {
+        PathResolver(self.path_interner.clone())
+    }
+}
+
+/// Resolves the path ids of a [`ShrRx`], see [`ShrRx::resolver`].
+#[derive(Debug, Clone)]
+pub struct PathResolver(Arc<PathInterner>);
+
+impl PathResolver {
+    /// Gets the path for the given `PathId`.
+    pub fn get_path(&self, id: PathId) -> Option<Arc<Path>> {
+        self.0.get(id)
+    }
+}
+
+/// The sender to feed a [`ShrRx`] with the events that are not scanned by
+/// [`Shr`], e.g. loaded from a file.
+#[derive(Debug)]
+pub struct ShrTx {
+    path_interner: Arc<PathInterner>,
+    tx: mpsc::UnboundedSender<Event>,
+    largest: Option<Arc<Largest>>,
+    /// The directories having subdirectories, which are not leaves.
+    non_leaves: HashSet<PathId>,
+}
+
+impl ShrTx {
+    /// Creates a connected pair of sender and receiver. The `largest` files
+    /// and leaf directories are tracked from the events sent, as
+    /// [`Shr::with_largest`] does.
+    pub fn channel(largest: Option<usize>) -> (ShrTx, ShrRx) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let path_interner = Arc::new(PathInterner::default());
+        let largest = largest.map(|k| Arc::new(Largest::new(k)));
+        let tx = ShrTx {
+            path_interner: path_interner.clone(),
+            tx,
+            largest: largest.clone(),
+            non_leaves: HashSet::new(),
+        };
+        let rx = ShrRx {
+            path_interner,
+            rx,
+            largest,
+        };
+        (tx, rx)
+    }
+
+    /// Interns a path to reference it in the events.
+    pub fn intern(&self, path: &Path) -> PathId {
+        self.path_interner.intern(&path.into())
+    }
+
+    /// Sends an event. It returns `false` if the receiver is dropped.
+    pub fn send(&mut self, event: Event) -> bool {
+        if let Some(largest) = &self.largest {
+            match &event {
+                Event::Dir {
+                    parent: Some(parent),
+                    ..
+                } => {
+                    self.non_leaves.insert(*parent);
+                }
+                Event::FileFinish { path, size, .. } => largest.add_file(*path, *size),
+                Event::DirFinish { path, size, .. } if !self.non_leaves.contains(path) => {
+                    largest.add_dir(*path, *size);
+                }
+                _ => {}
+            }
+        }
+        self.tx.send(event).is_ok()
+    }
 }
 
 pub(crate) struct Shared<'a> {
@@ -32,8 +128,28 @@ pub(crate) struct Shared<'a> {
     pub tx: mpsc::UnboundedSender<Event>,
     /// Whether to follow links.
     pub follow_links: bool,
+    /// Whether to aggregate the usage by owners.
+    pub owners: bool,
+    /// The clock to aggregate the usage by ages.
+    pub ages: Option<AgeClock>,
+    /// Whether to compute the fingerprints of directories.
+    pub fingerprints: bool,
+    /// The largest files and leaf directories, if requested.
+    pub largest: Option<Arc<Largest>>,
+    /// The budget of open file descriptors.
+    pub fds: FdBudget,
+    /// The rate limiter of metadata operations, if requested.
+    pub limiter: Option<RateLimiter>,
+    /// The strategy to walk the directories.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub walker: Walker,
+    /// The entries to skip.
+    pub excludes: Vec<Glob>,
 }
 
+/// The number of entries scanned by a job.
+pub(super) const CHUNK_SIZE: usize = 256;
+
 /// The main struct.
 pub(crate) struct ShrTask {
     /// The parent path id.
@@ -47,93 +163,161 @@ pub(crate) struct ShrTask {
 }
 
 impl ShrTask {
-    /// Executes the task.
-    pub fn exec(mut self, shared: &Shared) -> Option<(usize, u64)> {
+    /// Executes the task. It returns the summary of a file, or `None` if it is
+    /// a directory, which is scheduled and merged into the `parent` when all
+    /// its children are finished.
+    pub fn exec<'s>(
+        mut self,
+        scope: &Scope<'s>,
+        shared: &'s Shared<'s>,
+        parent: &Arc<PendingDir>,
+    ) -> Option<Summary> {
         loop {
-            let mt = std::fs::metadata(&self.path).report()?;
+            shared.throttle();
+            let Some(mt) = std::fs::metadata(&self.path).report() else {
+                return Some(Summary::unreadable());
+            };
             if mt.is_file() {
                 format_args!("scanning file: {:?}", self.path);
-                let file_size = mt.len();
-
-                if self.remain_report_depth > 0 {
-                    let event = Event::FileFinish {
-                        path: self.path_id,
-                        parent: self.parent,
-                        size: file_size,
-                    };
-                    let _ = shared.tx.send(event);
-                }
-                return Some((1, file_size));
+                return Some(self.finish_file(shared, &FileStat::from_metadata(&mt)));
             } else if mt.is_dir() {
                 format_args!("scanning dir: {:?}", self.path);
-                if self.remain_report_depth > 0 {
-                    let event = Event::Dir {
-                        path: self.path_id,
-                        parent: self.parent,
-                    };
-                    let _ = shared.tx.send(event);
-                }
-                return self.scan_dir(shared);
+                let dir = self.start_dir(shared, parent);
+                scope.spawn(move |scope| self.scan_dir(scope, shared, dir));
+                return None;
             } else if shared.follow_links && mt.is_symlink() {
                 format_args!("scanning link: {:?}", self.path);
                 // Follow the link
-                self.path = std::fs::read_link(&self.path).report()?.into();
+                let Some(target) = std::fs::read_link(&self.path).report() else {
+                    return Some(Summary::unreadable());
+                };
+                self.path = target.into();
             } else {
                 format_args!("skip: {:?}", self.path);
-                return Some((1, 0));
+                return Some(Summary::file(0, None, None));
             }
         }
     }
 
-    fn scan_dir(self, shared: &Shared) -> Option<(usize, u64)> {
-        let tx = shared.tx.clone();
-        let path_id = self.path_id;
-        let remain_report_depth = self.remain_report_depth;
-
-        let next_remain_report_depth = remain_report_depth.saturating_sub(1);
-        let (num_files, size) = std::fs::read_dir(self.path.clone())
-            .report()?
-            .par_bridge()
-            .fold(
-                || (0, 0),
-                |(num_files, size), entry| {
-                    let Ok(entry) = entry else {
-                        return (num_files, size);
-                    };
-
-                    let path = entry.path().into();
-                    let task = Self {
-                        remain_report_depth: next_remain_report_depth,
-                        path_id: shared.path_mgr.intern(&path),
-                        parent: Some(self.path_id),
-                        path,
-                    };
-
-                    let (sub_num_files, sub_size) = task.exec(shared).unwrap_or((0, 0));
-                    (num_files + sub_num_files, sub_size + size)
-                },
-            )
-            .reduce(
-                || (0, 0),
-                |(num_files_a, size_a), (num_files_b, size_b)| {
-                    (num_files_a + num_files_b, size_a + size_b)
-                },
-            );
-
-        if remain_report_depth > 0 {
-            let event = Event::DirFinish {
-                path: path_id,
-                size,
-                num_files,
+    /// Reports a file and gets its summary.
+    pub(super) fn finish_file(&self, shared: &Shared, stat: &FileStat) -> Summary {
+        let meta = shared.file_meta(&self.path, stat);
+        let summary = Summary::file(stat.size, meta.as_deref(), shared.ages.as_ref());
+        if let Some(largest) = &shared.largest {
+            largest.add_file(self.path_id, stat.size);
+        }
+
+        if self.remain_report_depth > 0 {
+            let event = Event::FileFinish {
+                path: self.path_id,
+                parent: self.parent,
+                size: stat.size,
+                meta,
+            };
+            let _ = shared.tx.send(event);
+        }
+        summary
+    }
+
+    /// Reports a directory and creates its pending state in the `parent`.
+    pub(super) fn start_dir(&self, shared: &Shared, parent: &Arc<PendingDir>) -> Arc<PendingDir> {
+        if self.remain_report_depth > 0 {
+            let event = Event::Dir {
+                path: self.path_id,
+                parent: self.parent,
             };
-            let _ = tx.send(event);
+            let _ = shared.tx.send(event);
         }
+        let name = self.path.file_name().unwrap_or_default().to_owned();
+        let report = self.remain_report_depth > 0;
+        PendingDir::child(parent, self.path_id, name, report)
+    }
 
-        Some((num_files, size))
+    /// Creates the task of an entry in the directory.
+    pub(super) fn child(&self, shared: &Shared, path: Arc<Path>) -> Self {
+        Self {
+            remain_report_depth: self.remain_report_depth.saturating_sub(1),
+            path_id: shared.path_mgr.intern(&path),
+            parent: Some(self.path_id),
+            path,
+        }
+    }
+
+    /// Lists the directory, and schedules its entries in chunks.
+    fn scan_dir<'s>(self, scope: &Scope<'s>, shared: &'s Shared<'s>, dir: Arc<PendingDir>) {
+        #[cfg(target_os = "linux")]
+        if shared.walker == Walker::Fd {
+            return super::linux::scan_dir_at(self, None, scope, shared, dir);
+        }
+
+        let entries = {
+            shared.throttle();
+            let _permit = shared.fds.acquire();
+            std::fs::read_dir(&self.path)
+                .report()
+                .map(|entries| entries.filter_map(Result::ok).collect::<Vec<_>>())
+        };
+        let Some(entries) = entries else {
+            dir.merge(Summary::unreadable());
+            dir.release(shared);
+            return;
+        };
+
+        let mut entries = entries.into_iter();
+        let mut chunk = entries.by_ref().take(CHUNK_SIZE).collect::<Vec<_>>();
+        loop {
+            let next = entries.by_ref().take(CHUNK_SIZE).collect::<Vec<_>>();
+            if next.is_empty() {
+                // Scans the last chunk in place.
+                self.scan_entries(chunk, scope, shared, &dir);
+                break;
+            }
+            dir.hold(1);
+            let task = self.clone_dir();
+            let dir = dir.clone();
+            scope.spawn(move |scope| {
+                task.scan_entries(chunk, scope, shared, &dir);
+                dir.release(shared);
+            });
+            chunk = next;
+        }
+        dir.release(shared);
+    }
+
+    /// Scans a chunk of entries in the directory.
+    fn scan_entries<'s>(
+        &self,
+        entries: Vec<std::fs::DirEntry>,
+        scope: &Scope<'s>,
+        shared: &'s Shared<'s>,
+        dir: &Arc<PendingDir>,
+    ) {
+        let mut summary = Summary::default();
+        for entry in entries {
+            let path = entry.path();
+            if shared.is_excluded(&path) {
+                continue;
+            }
+            let task = self.child(shared, path.into());
+            if let Some(child) = task.exec(scope, shared, dir) {
+                summary = summary.merge(child.into_child(&entry.file_name(), shared));
+            }
+        }
+        dir.merge(summary);
+    }
+
+    /// Clones the task to scan another chunk of the directory.
+    pub(super) fn clone_dir(&self) -> Self {
+        Self {
+            parent: self.parent,
+            path_id: self.path_id,
+            path: self.path.clone(),
+            remain_report_depth: self.remain_report_depth,
+        }
     }
 }
 
-trait Report {
+pub(crate) trait Report {
     type Target;
 
     fn report(self) -> Option<Self::Target>
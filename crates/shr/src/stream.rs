@@ -0,0 +1,218 @@
+//! The compact event stream, which defines each path once and references the
+//! path ids afterwards.
+//!
+//! The stream begins with a [`StreamRecord::Header`]. A
+//! [`StreamRecord::Path`] is emitted before the first event referencing the
+//! path id, whose `name` is the file name relative to the `parent`, or the
+//! full path if it has no parent. The events are emitted as is, e.g. in JSON
+//! lines:
+//!
+//! ```json
+//! {"type":"header","format":"shr-stream","version":1}
+//! {"type":"path","id":1,"parent":null,"name":"/tmp/foo"}
+//! {"type":"dir","path":1,"parent":null}
+//! {"type":"path","id":2,"parent":1,"name":"bar.txt"}
+//! {"type":"fileFinish","path":2,"parent":1,"size":3}
+//! {"type":"dirFinish","path":1,"size":3,"num_files":1}
+//! {"type":"total","size":3,"num_files":1}
+//! ```
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "tokio")]
+use crate::ShrTx;
+use crate::{Event, ImmutPath, PathId};
+
+/// The name of the stream format in the header.
+pub const STREAM_FORMAT: &str = "shr-stream";
+/// The version of the stream schema, which is bumped on incompatible changes.
+pub const STREAM_VERSION: u32 = 1;
+
+/// A record of the compact event stream.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum StreamRecord {
+    /// The first record of the stream.
+    Header {
+        /// The name of the format, which is [`STREAM_FORMAT`].
+        format: String,
+        /// The version of the schema, see [`STREAM_VERSION`].
+        version: u32,
+    },
+    /// Defines a path id before it is referenced.
+    Path {
+        /// The path id.
+        id: PathId,
+        /// The parent path id.
+        parent: Option<PathId>,
+        /// The file name, or the full path if it has no parent.
+        name: ImmutPath,
+    },
+    /// An event referencing the defined path ids.
+    #[serde(untagged)]
+    Event(Event),
+}
+
+impl StreamRecord {
+    /// Creates the header of the current version.
+    pub fn header() -> Self {
+        Self::Header {
+            format: STREAM_FORMAT.to_owned(),
+            version: STREAM_VERSION,
+        }
+    }
+}
+
+/// Encodes the events into the stream records, defining the paths on demand.
+#[derive(Debug, Default)]
+pub struct StreamEncoder {
+    /// The path ids defined so far.
+    defined: HashSet<PathId>,
+}
+
+impl StreamEncoder {
+    /// Encodes an event, and pushes the records to `out`. The `get_path`
+    /// resolves the path ids, e.g. by [`crate::ShrRx::get_path`].
+    pub fn encode(
+        &mut self,
+        event: Event,
+        get_path: impl Fn(PathId) -> Option<Arc<Path>>,
+        out: &mut Vec<StreamRecord>,
+    ) {
+        let (path, parent) = match &event {
+            Event::Dir { path, parent } | Event::FileFinish { path, parent, .. } => {
+                (Some(*path), *parent)
+            }
+            Event::DirFinish { path, .. } => (Some(*path), None),
+            Event::Total { .. } => (None, None),
+        };
+        // The parent is defined first if the encoder joins in the middle of
+        // a scan, e.g. when an event stream is subscribed late.
+        for (id, parent) in [(parent, None), (path, parent)] {
+            let Some(id) = id.filter(|id| !self.defined.contains(id)) else {
+                continue;
+            };
+            let Some(path) = get_path(id) else {
+                continue;
+            };
+            // Only the defined parent is referenced, otherwise the full path
+            // is used as the name.
+            let parent = parent.filter(|parent| self.defined.contains(parent));
+            let (parent, name) = match (parent, path.file_name()) {
+                (Some(parent), Some(name)) => (Some(parent), Path::new(name).into()),
+                _ => (None, path),
+            };
+            out.push(StreamRecord::Path {
+                id,
+                parent,
+                name: ImmutPath(name),
+            });
+            self.defined.insert(id);
+        }
+        out.push(StreamRecord::Event(event));
+    }
+}
+
+/// Decodes the stream records into a [`ShrTx`], interning the paths defined
+/// by the stream as local path ids.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct StreamDecoder {
+    tx: ShrTx,
+    /// The local path ids and the full paths, by the ids in the stream.
+    paths: std::collections::HashMap<PathId, (PathId, Arc<Path>)>,
+    /// Whether the header is decoded.
+    header: bool,
+}
+
+#[cfg(feature = "tokio")]
+impl StreamDecoder {
+    /// Creates a decoder sending the events to `tx`.
+    pub fn new(tx: ShrTx) -> Self {
+        Self {
+            tx,
+            paths: Default::default(),
+            header: false,
+        }
+    }
+
+    /// Decodes a record, and sends the event if it is one. It returns
+    /// `Ok(false)` if the receiver is dropped.
+    pub fn decode(&mut self, record: StreamRecord) -> anyhow::Result<bool> {
+        use anyhow::bail;
+
+        match record {
+            StreamRecord::Header { format, version } => {
+                if format != STREAM_FORMAT || version != STREAM_VERSION {
+                    bail!("unsupported stream: {format} version {version}");
+                }
+                self.header = true;
+            }
+            _ if !self.header => bail!("the stream does not begin with a header"),
+            StreamRecord::Path { id, parent, name } => {
+                let path: Arc<Path> = match parent {
+                    Some(parent) => self.path(parent)?.join(&name.0).into(),
+                    None => name.0,
+                };
+                let local = self.tx.intern(&path);
+                self.paths.insert(id, (local, path));
+            }
+            StreamRecord::Event(event) => {
+                let parent = |this: &Self, parent: Option<PathId>| {
+                    parent.map(|parent| this.local(parent)).transpose()
+                };
+                let event = match event {
+                    Event::Dir { path, parent: p } => Event::Dir {
+                        path: self.local(path)?,
+                        parent: parent(self, p)?,
+                    },
+                    Event::FileFinish {
+                        path,
+                        parent: p,
+                        size,
+                        meta,
+                    } => Event::FileFinish {
+                        path: self.local(path)?,
+                        parent: parent(self, p)?,
+                        size,
+                        meta,
+                    },
+                    Event::DirFinish {
+                        path,
+                        size,
+                        num_files,
+                        meta,
+                    } => Event::DirFinish {
+                        path: self.local(path)?,
+                        size,
+                        num_files,
+                        meta,
+                    },
+                    total @ Event::Total { .. } => total,
+                };
+                return Ok(self.tx.send(event));
+            }
+        }
+        Ok(true)
+    }
+
+    /// Gets the local path id of the id in the stream.
+    fn local(&self, id: PathId) -> anyhow::Result<PathId> {
+        self.paths
+            .get(&id)
+            .map(|(local, _)| *local)
+            .ok_or_else(|| anyhow::anyhow!("undefined path id: {}", id.into_raw()))
+    }
+
+    /// Gets the full path of the id in the stream.
+    fn path(&self, id: PathId) -> anyhow::Result<&Arc<Path>> {
+        self.paths
+            .get(&id)
+            .map(|(_, path)| path)
+            .ok_or_else(|| anyhow::anyhow!("undefined path id: {}", id.into_raw()))
+    }
+}
@@ -6,7 +6,7 @@ static UNITS: [char; 5] = ['P', 'T', 'G', 'M', 'K'];
 
 /// Converts a number to a human-readable format.
 /// output_str: `si` for SI units, `bi` for binary units, or `b` for bytes.
-pub fn human_readable_number(size: u64, output_str: &str) -> Hr {
+pub fn human_readable_number(size: u64, output_str: &str) -> Hr<'_> {
     Hr(size, output_str)
 }
 
@@ -62,3 +62,30 @@ fn get_number_format(output_str: &str) -> Option<(u64, char)> {
     }
     None
 }
+
+/// Parses a human-readable size like `512`, `1.5M` or `20GiB` in bytes. The
+/// units are always binary, e.g. `1K` is 1024 bytes, as printed by
+/// [`human_readable_number`] in the binary format (`""`) but not in `"si"`.
+pub fn parse_human_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size: {s}"))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let exp = match unit {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        _ => return Err(format!("invalid size unit: {s}")),
+    };
+    Ok((number * 1024f64.powi(exp)) as u64)
+}
@@ -0,0 +1,72 @@
+//! Streams a scan to a client over localhost.
+
+use std::path::Path;
+
+use shr::{Event, Shr, ShrRx};
+
+/// Creates a small tree with nested directories.
+fn create_tree(root: &Path) {
+    std::fs::create_dir_all(root.join("a/b")).unwrap();
+    std::fs::create_dir_all(root.join("c")).unwrap();
+    std::fs::write(root.join("f"), b"shr").unwrap();
+    std::fs::write(root.join("a/g"), b"hunts").unwrap();
+    std::fs::write(root.join("a/b/h"), b"disk space").unwrap();
+}
+
+/// Collects the events with the paths resolved, sorted for comparison.
+async fn collect(rx: &mut ShrRx) -> Vec<String> {
+    let mut events = Vec::new();
+    let paths = rx.resolver();
+    let path = |id| paths.get_path(id).unwrap().display().to_string();
+    while let Some(event) = rx.recv().await {
+        let event = match event.to_raw() {
+            Event::Dir { path: id, parent } => {
+                format!("dir {} in {:?}", path(id), parent.map(path))
+            }
+            Event::FileFinish {
+                path: id,
+                parent,
+                size,
+                ..
+            } => format!("file {} in {:?}: {size}", path(id), parent.map(path)),
+            Event::DirFinish {
+                path: id,
+                size,
+                num_files,
+                ..
+            } => format!("finish {}: {size}, {num_files}", path(id)),
+            Event::Total {
+                size, num_files, ..
+            } => format!("total: {size}, {num_files}"),
+        };
+        events.push(event);
+    }
+    events.sort();
+    events
+}
+
+#[tokio::test]
+async fn stream_over_localhost() {
+    let root = std::env::temp_dir().join(format!("shr-agent-{}", std::process::id()));
+    create_tree(&root);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let agent = tokio::spawn({
+        let root = root.clone();
+        async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut rx = Shr::new(root).run().await;
+            shr::serve_agent(&mut rx, stream).await.unwrap();
+        }
+    });
+
+    let mut remote = shr::connect_agent(addr, None).await.unwrap();
+    let remote = collect(&mut remote).await;
+    agent.await.unwrap();
+    let local = collect(&mut Shr::new(root.clone()).run().await).await;
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(remote, local);
+    assert!(remote.contains(&"total: 18, 3".to_owned()));
+}
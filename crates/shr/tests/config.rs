@@ -0,0 +1,79 @@
+//! Loads the config files, and skips the excluded entries.
+
+use std::path::Path;
+
+use shr::{Config, Event, Glob, Shr};
+
+/// Creates a file with the content, and its parent directories.
+fn write(path: &Path, content: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+#[test]
+fn load_config_and_profile() {
+    let root = std::env::temp_dir().join(format!("shr-config-{}", std::process::id()));
+    let user = root.join("user.toml");
+    write(
+        &user,
+        "exclude = [\"target\"]\nunits = \"binary\"\nthreads = 8\n[profile.ci]\nthreads = 1\n",
+    );
+    write(
+        &root.join("project").join(".shr.toml"),
+        "exclude = [\"node_modules\"]\nthreads = 4\n[profile.ci]\nformat = \"json\"\n",
+    );
+    std::fs::create_dir_all(root.join("project").join("src")).unwrap();
+
+    // SAFETY: no other test of this binary reads the environment.
+    unsafe { std::env::set_var("SHR_CONFIG", &user) };
+    let cwd = std::env::current_dir().unwrap();
+    std::env::set_current_dir(root.join("project").join("src")).unwrap();
+    let config = Config::load(None);
+    let profiled = Config::load(Some("ci"));
+    let unknown = Config::load(Some("nope"));
+    std::env::set_current_dir(cwd).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    // The per-directory config overrides the user-level one, and the excludes
+    // are combined.
+    let config = config.unwrap();
+    assert_eq!(config.exclude, ["target", "node_modules"]);
+    assert_eq!(config.units.as_deref(), Some("binary"));
+    assert_eq!(config.threads, Some(4));
+    assert_eq!(config.format, None);
+
+    // The profiles override the top level of both files.
+    let profiled = profiled.unwrap();
+    assert_eq!(profiled.threads, Some(1));
+    assert_eq!(profiled.format.as_deref(), Some("json"));
+
+    assert!(unknown.is_err());
+}
+
+#[tokio::test]
+async fn scan_with_excludes() {
+    let root = std::env::temp_dir().join(format!("shr-exclude-{}", std::process::id()));
+    write(&root.join("src").join("main.rs"), "fn main() {}");
+    write(&root.join("node_modules").join("a").join("index.js"), "a");
+    write(&root.join("src").join("node_modules").join("b.js"), "b");
+    write(&root.join("build.log"), "log");
+
+    let excludes = ["node_modules", "*.log"].map(Glob::new).to_vec();
+    let mut rx = Shr::new(root.clone())
+        .with_threads(2)
+        .with_excludes(excludes)
+        .run()
+        .await;
+    let (mut files, mut total) = (Vec::new(), None);
+    while let Some(event) = rx.recv().await {
+        match event.to_raw() {
+            Event::FileFinish { path, .. } => files.push(path),
+            Event::Total { num_files, .. } => total = Some(num_files),
+            _ => {}
+        }
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(files.len(), 1);
+    assert_eq!(total, Some(1));
+}
@@ -0,0 +1,69 @@
+//! Scans a pathologically deep directory tree.
+
+use std::path::{Path, PathBuf};
+
+use shr::{Event, Shr};
+
+/// The depth of the fixture.
+const DEPTH: usize = 10_000;
+
+/// Creates a chain of `depth` nested directories named `d`, each with a file.
+/// The paths exceed `PATH_MAX`, so the chain is created relative to the
+/// current directory.
+fn create_chain(root: &Path, depth: usize) {
+    let cwd = std::env::current_dir().unwrap();
+    std::fs::create_dir_all(root).unwrap();
+    std::env::set_current_dir(root).unwrap();
+    for _ in 0..depth {
+        std::fs::create_dir("d").unwrap();
+        std::env::set_current_dir("d").unwrap();
+        std::fs::write("f", b"shr").unwrap();
+    }
+    std::env::set_current_dir(cwd).unwrap();
+}
+
+/// Removes the chain from the bottom up without recursion.
+fn remove_chain(root: &Path, depth: usize) {
+    let cwd = std::env::current_dir().unwrap();
+    std::env::set_current_dir(root).unwrap();
+    for _ in 0..depth {
+        std::env::set_current_dir("d").unwrap();
+    }
+    for _ in 0..depth {
+        std::fs::remove_file("f").unwrap();
+        std::env::set_current_dir("..").unwrap();
+        std::fs::remove_dir("d").unwrap();
+    }
+    std::env::set_current_dir(cwd).unwrap();
+    std::fs::remove_dir(root).unwrap();
+}
+
+#[tokio::test]
+async fn scan_deep_tree() {
+    let root = std::env::temp_dir().join(format!("shr-deep-tree-{}", std::process::id()));
+    create_chain(&root, DEPTH);
+
+    let mut rx = Shr::new(PathBuf::from(&root)).with_threads(2).run().await;
+    let (mut dirs, mut finished, mut files, mut total) = (0, 0, 0, None);
+    while let Some(event) = rx.recv().await {
+        match event.to_raw() {
+            Event::Dir { .. } => dirs += 1,
+            Event::DirFinish { .. } => finished += 1,
+            Event::FileFinish { .. } => files += 1,
+            Event::Total { num_files, .. } => total = Some(num_files),
+        }
+    }
+    remove_chain(&root, DEPTH);
+
+    // Every directory reached must be finished.
+    assert_eq!(dirs, finished);
+    assert_eq!(total, Some(files));
+    // The paths longer than `PATH_MAX` can only be scanned relative to their
+    // parents.
+    if cfg!(target_os = "linux") {
+        assert_eq!(dirs, DEPTH + 1);
+        assert_eq!(files, DEPTH);
+    } else {
+        assert!(dirs > 1);
+    }
+}
@@ -0,0 +1,143 @@
+//! Reads the serialized event streams as if the scan ran locally.
+
+use std::io::Cursor;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use shr::{Event, LogFormat, Replay, Shr, ShrRx, StreamEncoder, StreamRecord};
+
+/// Creates a small tree with nested directories.
+fn create_tree(root: &Path) {
+    std::fs::create_dir_all(root.join("a/b")).unwrap();
+    std::fs::create_dir_all(root.join("c")).unwrap();
+    std::fs::write(root.join("f"), b"shr").unwrap();
+    std::fs::write(root.join("a/g"), b"hunts").unwrap();
+    std::fs::write(root.join("a/b/h"), b"disk space").unwrap();
+}
+
+/// Collects the events with the paths resolved, sorted for comparison.
+async fn collect(rx: &mut ShrRx) -> Vec<String> {
+    let mut events = Vec::new();
+    let paths = rx.resolver();
+    let path = |id| paths.get_path(id).unwrap().display().to_string();
+    while let Some(event) = rx.recv().await {
+        let event = match event.to_raw() {
+            Event::Dir { path: id, parent } => {
+                format!("dir {} in {:?}", path(id), parent.map(path))
+            }
+            Event::FileFinish {
+                path: id,
+                parent,
+                size,
+                ..
+            } => format!("file {} in {:?}: {size}", path(id), parent.map(path)),
+            Event::DirFinish {
+                path: id,
+                size,
+                num_files,
+                ..
+            } => format!("finish {}: {size}, {num_files}", path(id)),
+            Event::Total {
+                size, num_files, ..
+            } => format!("total: {size}, {num_files}"),
+        };
+        events.push(event);
+    }
+    events.sort();
+    events
+}
+
+/// Serializes a scan in JSON lines with the full paths.
+async fn json_lines(root: &Path) -> String {
+    let mut rx = Shr::new(root.to_owned()).run().await;
+    let mut lines = String::new();
+    while let Some(event) = rx.recv().await {
+        lines += &serde_json::to_string(&event).unwrap();
+        lines.push('\n');
+    }
+    lines
+}
+
+/// Serializes a scan in the compact event stream.
+async fn stream_lines(root: &Path) -> String {
+    let mut rx = Shr::new(root.to_owned()).run().await;
+    let mut encoder = StreamEncoder::default();
+    let mut records = vec![StreamRecord::header()];
+    while let Some(event) = rx.recv().await {
+        let event = event.to_raw();
+        encoder.encode(event, |id| rx.get_path(id), &mut records);
+    }
+    records
+        .iter()
+        .map(|record| serde_json::to_string(record).unwrap() + "\n")
+        .collect()
+}
+
+#[tokio::test]
+async fn read_json_and_stream() {
+    let root = std::env::temp_dir().join(format!("shr-replay-{}", std::process::id()));
+    create_tree(&root);
+
+    let local = collect(&mut Shr::new(root.clone()).run().await).await;
+    let json = json_lines(&root).await;
+    let stream = stream_lines(&root).await;
+    std::fs::remove_dir_all(&root).unwrap();
+
+    for lines in [json, stream] {
+        let mut rx = shr::read_events(Cursor::new(lines), None).unwrap();
+        assert_eq!(collect(&mut rx).await, local);
+    }
+    assert!(local.contains(&"total: 18, 3".to_owned()));
+    assert!(shr::read_events(Cursor::new("\n"), None).is_err());
+}
+
+#[tokio::test]
+async fn record_and_replay() {
+    let root = std::env::temp_dir().join(format!("shr-record-{}", std::process::id()));
+    create_tree(&root);
+
+    let local = collect(&mut Shr::new(root.clone()).run().await).await;
+    let mut logs = Vec::new();
+    for format in [LogFormat::Json, LogFormat::Binary] {
+        let mut log = Vec::new();
+        let mut rx = Shr::new(root.clone()).run().await;
+        shr::record_events(&mut rx, &mut log, format).await.unwrap();
+        logs.push(log);
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+
+    for log in logs {
+        let mut rx = Replay::new(Cursor::new(log)).run().unwrap();
+        assert_eq!(collect(&mut rx).await, local);
+    }
+    assert!(Replay::new(Cursor::new("")).run().is_err());
+}
+
+#[tokio::test]
+async fn replay_with_timing() {
+    let log = r#"{"t":0,"type":"header","format":"shr-stream","version":1}
+{"t":0,"type":"path","id":7,"parent":null,"name":"/replayed"}
+{"t":0,"type":"dir","path":7,"parent":null}
+{"t":100,"type":"path","id":8,"parent":7,"name":"file"}
+{"t":100,"type":"fileFinish","path":8,"parent":7,"size":42}
+{"t":300,"type":"dirFinish","path":7,"size":42,"num_files":1}
+{"t":300,"type":"total","size":42,"num_files":1}
+"#;
+
+    let begin = Instant::now();
+    let mut rx = Replay::new(Cursor::new(log))
+        .with_timing(true)
+        .run()
+        .unwrap();
+    let events = collect(&mut rx).await;
+    assert!(begin.elapsed() >= Duration::from_millis(300));
+    assert_eq!(
+        events,
+        [
+            "dir /replayed in None",
+            "file /replayed/file in Some(\"/replayed\"): 42",
+            "finish /replayed: 42, 1",
+            "total: 42, 1",
+        ]
+    );
+}
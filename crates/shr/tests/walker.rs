@@ -0,0 +1,54 @@
+//! Compares the walkers on a wide tree, with and without enough file
+//! descriptors to keep the directories open.
+
+use std::path::Path;
+
+use shr::{Event, Shr, Walker};
+
+/// Creates directories with more entries than a chunk, nested a few levels.
+fn create_tree(root: &Path) {
+    for dir in ["a", "a/b", "a/b/c", "d"] {
+        let dir = root.join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..300 {
+            std::fs::write(dir.join(format!("f{i}")), vec![0; i]).unwrap();
+        }
+    }
+}
+
+/// Scans the tree, and gets the size and the numbers of the files and the
+/// directories.
+async fn scan(root: &Path, walker: Walker, open_files: usize) -> (u64, usize, usize) {
+    let mut rx = Shr::new(root.to_owned())
+        .with_walker(walker)
+        .with_threads(4)
+        .with_open_files(open_files)
+        .run()
+        .await;
+    let (mut total, mut dirs) = ((0, 0), 0);
+    while let Some(event) = rx.recv().await {
+        match event.to_raw() {
+            Event::DirFinish { .. } => dirs += 1,
+            Event::Total {
+                size, num_files, ..
+            } => total = (size, num_files),
+            _ => {}
+        }
+    }
+    (total.0, total.1, dirs)
+}
+
+#[tokio::test]
+async fn walkers_agree() {
+    let root = std::env::temp_dir().join(format!("shr-walker-{}", std::process::id()));
+    create_tree(&root);
+    let by_path = scan(&root, Walker::Path, 256).await;
+    let by_fd = scan(&root, Walker::Fd, 256).await;
+    let by_fd_exhausted = scan(&root, Walker::Fd, 2).await;
+    std::fs::remove_dir_all(&root).unwrap();
+
+    let size = 4 * (0..300).sum::<usize>() as u64;
+    assert_eq!(by_path, (size, 4 * 300, 5));
+    assert_eq!(by_fd, by_path);
+    assert_eq!(by_fd_exhausted, by_path);
+}